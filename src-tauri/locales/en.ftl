@@ -0,0 +1,28 @@
+history-tag = 📝 Clipboard History
+char-count = { $count ->
+    [one] { $count } character
+   *[other] { $count } characters
+}
+
+tray-about = About
+tray-quit = Quit Drawer
+about-description = Drawer is a clipboard manager for macOS and Linux
+
+error-not-found = The item does not exist or has been deleted
+error-delete-history = Clipboard history cannot be deleted
+error-move-history = Clipboard history cannot be moved
+error-reorder-history = Clipboard history cannot be reordered
+error-nest-under-history = Tags cannot be created under clipboard history
+error-link-history = Clipboard history cannot be linked to a directory
+error-snippet-in-history = Snippets cannot be created in clipboard history
+error-not-snippet = Not a snippet
+error-snippet-not-text = Snippets can only contain text
+error-linked-read-only = Snippets from a linked folder are read-only
+error-invalid-argument = Invalid argument: { $detail }
+error-invalid-format = Invalid data format
+error-storage-failure = Failed to read or write data
+error-clipboard-unavailable = Cannot access the clipboard
+error-paste-failed = Could not paste automatically, the content has been copied to the clipboard
+error-type-interrupted = Typing stopped after { $chars } characters
+error-sync-failure = Sync failed
+error-internal = Internal error
@@ -0,0 +1,25 @@
+history-tag = 📝 剪贴板历史
+char-count = { $count } 个字符
+
+tray-about = 关于
+tray-quit = 退出 Drawer
+about-description = Drawer是一个macOS和Linux上的剪贴板应用
+
+error-not-found = 内容不存在或已被删除
+error-delete-history = 剪贴板历史不能删除
+error-move-history = 剪贴板历史不能移动
+error-reorder-history = 剪贴板历史不能调整顺序
+error-nest-under-history = 剪贴板历史下不能创建子标签
+error-link-history = 剪贴板历史不能关联目录
+error-snippet-in-history = 剪贴板历史下不能创建片段
+error-not-snippet = 不是片段
+error-snippet-not-text = 片段只能是文本
+error-linked-read-only = 关联目录中的片段是只读的
+error-invalid-argument = 参数不正确：{ $detail }
+error-invalid-format = 数据格式不正确
+error-storage-failure = 读写数据失败
+error-clipboard-unavailable = 无法访问剪贴板
+error-paste-failed = 无法自动粘贴，内容已复制到剪贴板
+error-type-interrupted = 已输入 { $chars } 个字符后中断
+error-sync-failure = 同步失败
+error-internal = 内部错误
@@ -0,0 +1,477 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs::File, io::{self, Read, Write}, path::Path, result};
+
+use chrono::Local;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use zip::{ZipArchive, ZipWriter, write::FileOptions, CompressionMethod};
+
+use crate::{storage::{StorageConn, StorageError, StorageTx, ClipboardRecord, TagRecord}, clipboard::{ClipboardContent, ClipboardFormatContent}};
+
+/// 归档格式标识和版本，格式不兼容时递增版本
+pub const ARCHIVE_FORMAT: &str = "drawer-archive";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_NAME: &str = "manifest.json";
+const BLOB_DIR: &str = "blobs";
+
+/// 包装导入导出过程中的错误
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    Zip(zip::result::ZipError),
+    Serde(serde_json::Error),
+    Storage(StorageError),
+    /// 归档内容不合法，如格式版本不支持、blob 校验失败
+    Invalid(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(err) => write!(f, "io error: {}", err),
+            ArchiveError::Zip(err) => write!(f, "zip error: {}", err),
+            ArchiveError::Serde(err) => write!(f, "serde error: {}", err),
+            ArchiveError::Storage(err) => write!(f, "{}", err),
+            ArchiveError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(value: io::Error) -> Self {
+        ArchiveError::Io(value)
+    }
+}
+
+impl From<zip::result::ZipError> for ArchiveError {
+    fn from(value: zip::result::ZipError) -> Self {
+        ArchiveError::Zip(value)
+    }
+}
+
+impl From<serde_json::Error> for ArchiveError {
+    fn from(value: serde_json::Error) -> Self {
+        ArchiveError::Serde(value)
+    }
+}
+
+impl From<StorageError> for ArchiveError {
+    fn from(value: StorageError) -> Self {
+        ArchiveError::Storage(value)
+    }
+}
+
+pub type Result<T> = result::Result<T, ArchiveError>;
+
+/// 归档清单，记录的内容按 sha256 存放在 blobs/ 下
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String,
+    pub exported_at: i64,
+    pub tags: Vec<ManifestTag>,
+    pub records: Vec<ManifestRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ManifestTag {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// 同一标签下的记录按在清单中的顺序排列
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ManifestRecord {
+    pub tag_id: i64,
+    pub content_type: String,
+    pub time: i64,
+    pub hash: String,
+    pub secret: bool,
+}
+
+/// 归档中记录的一种格式，图片只保存 blob 的哈希
+#[derive(Serialize, Deserialize, Debug)]
+enum ArchivedFormat {
+    Text(String),
+    Rtf(Vec<u8>),
+    Image { blob: String },
+    Files(Vec<String>),
+}
+
+/// 归档中记录的内容，与 ClipboardContent 对应
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedContent {
+    main_format: ArchivedFormat,
+    data: Vec<ArchivedFormat>,
+}
+
+/// 导入结果
+#[derive(Serialize, Default, Debug)]
+pub struct ImportReport {
+    pub tags_created: usize,
+    pub records_imported: usize,
+    /// 数据库或归档中已有相同内容而跳过的记录
+    pub records_skipped: usize,
+}
+
+/// 内容哈希，用于去重和校验
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn blob_name(hash: &str) -> String {
+    format!("{}/{}", BLOB_DIR, hash)
+}
+
+/// 导出标签及其子孙标签下的记录到 path，tag_ids 为 None 时导出全部
+pub fn export(db: &StorageConn, path: &Path, tag_ids: Option<&[i64]>, app_version: &str) -> Result<Manifest> {
+    let all_tags = db.get_tags()?;
+    let tags: Vec<TagRecord> = match tag_ids {
+        None => all_tags,
+        Some(tag_ids) => {
+            let mut selected = HashSet::new();
+            for id in tag_ids {
+                selected.insert(*id);
+                for tag in db.get_descendant_tags(*id)? {
+                    selected.insert(tag.id);
+                }
+            }
+            all_tags.into_iter().filter(|tag| selected.contains(&tag.id)).collect()
+        }
+    };
+    let exported_ids: HashSet<i64> = tags.iter().map(|tag| tag.id).collect();
+
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let mut written_blobs = HashSet::new();
+    let mut write_blob = |zip: &mut ZipWriter<File>, data: &[u8]| -> Result<String> {
+        let hash = content_hash(data);
+        if written_blobs.insert(hash.clone()) {
+            zip.start_file(blob_name(&hash), options)?;
+            zip.write_all(data)?;
+        }
+        Ok(hash)
+    };
+    let mut records = Vec::new();
+    for tag in &tags {
+        for record in db.get_local_records_with_tag(tag.id)? {
+            let content: ClipboardContent = serde_json::from_str(&record.data)?;
+            let mut archive_format = |format| -> Result<ArchivedFormat> {
+                Ok(match format {
+                    ClipboardFormatContent::Text(text) => ArchivedFormat::Text(text),
+                    ClipboardFormatContent::RTF(rtf) => ArchivedFormat::Rtf(rtf),
+                    ClipboardFormatContent::Image(image) => ArchivedFormat::Image { blob: write_blob(&mut zip, &image)? },
+                    ClipboardFormatContent::Files(files) => ArchivedFormat::Files(files),
+                })
+            };
+            let archived = ArchivedContent {
+                main_format: archive_format(content.main_format)?,
+                data: content.data.into_iter().map(&mut archive_format).collect::<Result<_>>()?,
+            };
+            let hash = write_blob(&mut zip, serde_json::to_string(&archived)?.as_bytes())?;
+            records.push(ManifestRecord {
+                tag_id: tag.id,
+                content_type: record.content_type,
+                time: record.time,
+                hash,
+                secret: record.secret,
+            });
+        }
+    }
+
+    let manifest = Manifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        app_version: app_version.to_string(),
+        exported_at: Local::now().timestamp_millis(),
+        tags: tags.into_iter().map(|tag| ManifestTag {
+            id: tag.id,
+            name: tag.name,
+            // 父标签没有被导出时作为顶层标签
+            parent_id: tag.parent_id.filter(|id| exported_ids.contains(id)),
+            color: tag.color,
+            icon: tag.icon,
+        }).collect(),
+        records,
+    };
+    zip.start_file(MANIFEST_NAME, options)?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    zip.finish()?;
+    Ok(manifest)
+}
+
+/// 把归档合并到当前数据库：同名同父的标签合并，数据库中已有相同内容的记录跳过。
+/// 所有修改在一个事务中完成，出错时数据库保持不变
+pub fn import(db: &StorageConn, path: &Path) -> Result<ImportReport> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+    let manifest: Manifest = {
+        let mut buf = String::new();
+        zip.by_name(MANIFEST_NAME)?.read_to_string(&mut buf)?;
+        serde_json::from_str(&buf)?
+    };
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(ArchiveError::Invalid(format!("unknown archive format {}", manifest.format)));
+    }
+    if manifest.version != ARCHIVE_VERSION {
+        return Err(ArchiveError::Invalid(format!("unsupported archive version {}", manifest.version)));
+    }
+
+    db.transaction(|tx| {
+        let mut report = ImportReport::default();
+        let tag_map = import_tags(tx, &manifest.tags, &mut report)?;
+
+        // 已有记录所在的标签和内容哈希，只和导入到同一个标签下的记录比较
+        let mut existing = HashSet::new();
+        for (tag_id, record) in tx.get_all_local_records()? {
+            existing.insert((tag_id, content_hash(record.data.as_bytes())));
+        }
+        for r in &manifest.records {
+            let tag_id = match tag_map.get(&r.tag_id) {
+                Some(id) => *id,
+                None => return Err(ArchiveError::Invalid(format!("record refers to unknown tag {}", r.tag_id))),
+            };
+            let mut record: ClipboardRecord = read_content(&mut zip, &r.hash)?.try_into()?;
+            if !existing.insert((tag_id, content_hash(record.data.as_bytes()))) {
+                report.records_skipped += 1;
+                continue;
+            }
+            record.time = r.time;
+            record.secret = r.secret;
+            tx.insert_record_with_tag(record, tag_id)?;
+            report.records_imported += 1;
+        }
+        Ok(report)
+    })
+}
+
+/// 读取 blob 并校验哈希
+fn read_blob(zip: &mut ZipArchive<File>, hash: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    zip.by_name(&blob_name(hash))?.read_to_end(&mut data)?;
+    if content_hash(&data) != hash {
+        return Err(ArchiveError::Invalid(format!("blob {} is corrupted", hash)));
+    }
+    Ok(data)
+}
+
+/// 读取一条记录的内容
+fn read_content(zip: &mut ZipArchive<File>, hash: &str) -> Result<ClipboardContent> {
+    let data = read_blob(zip, hash)?;
+    let archived: ArchivedContent = serde_json::from_slice(&data)?;
+    let mut restore_format = |format| -> Result<ClipboardFormatContent> {
+        Ok(match format {
+            ArchivedFormat::Text(text) => ClipboardFormatContent::Text(text),
+            ArchivedFormat::Rtf(rtf) => ClipboardFormatContent::RTF(rtf),
+            ArchivedFormat::Image { blob } => ClipboardFormatContent::Image(read_blob(zip, &blob)?),
+            ArchivedFormat::Files(files) => ClipboardFormatContent::Files(files),
+        })
+    };
+    Ok(ClipboardContent {
+        main_format: restore_format(archived.main_format)?,
+        data: archived.data.into_iter().map(&mut restore_format).collect::<Result<_>>()?,
+    })
+}
+
+/// 导入标签，返回归档中的标签 id 到本地标签 id 的映射
+fn import_tags(tx: &StorageTx, tags: &[ManifestTag], report: &mut ImportReport) -> Result<HashMap<i64, i64>> {
+    let mut local_tags = tx.get_tags()?;
+    // 剪贴板历史固定为 0
+    let mut tag_map = HashMap::from([(0, 0)]);
+    let mut pending: Vec<&ManifestTag> = tags.iter().filter(|tag| tag.id != 0).collect();
+    while !pending.is_empty() {
+        let before = pending.len();
+        let mut rest = Vec::new();
+        for tag in pending {
+            // 父标签先导入
+            let parent_id = match tag.parent_id {
+                None => None,
+                Some(id) => match tag_map.get(&id) {
+                    Some(local_id) => Some(*local_id),
+                    None if tags.iter().any(|t| t.id == id) => {
+                        rest.push(tag);
+                        continue;
+                    }
+                    None => None,
+                },
+            };
+            let local = local_tags.iter()
+                .find(|local| local.name == tag.name && local.parent_id == parent_id)
+                .map(|local| local.id);
+            let local_id = match local {
+                Some(id) => id,
+                None => {
+                    let created = tx.insert_tag(tag.name.clone(), parent_id, tag.color.clone(), tag.icon.clone())?;
+                    report.tags_created += 1;
+                    let id = created.id;
+                    local_tags.push(created);
+                    id
+                }
+            };
+            tag_map.insert(tag.id, local_id);
+        }
+        if rest.len() == before {
+            return Err(ArchiveError::Invalid("tags contain a cycle".to_string()));
+        }
+        pending = rest;
+    }
+    Ok(tag_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(format: ClipboardFormatContent) -> ClipboardRecord {
+        ClipboardContent { main_format: format.clone(), data: vec![format] }.try_into().unwrap()
+    }
+
+    fn text(text: &str) -> ClipboardRecord {
+        record(ClipboardFormatContent::Text(text.to_string()))
+    }
+
+    fn tag_id(db: &StorageConn, name: &str) -> i64 {
+        db.get_tags().unwrap().into_iter().find(|tag| tag.name == name).unwrap().id
+    }
+
+    fn contents(db: &StorageConn, tag_id: i64) -> Vec<ClipboardContent> {
+        db.get_records_with_tag(tag_id).unwrap().into_iter().map(|record| record.try_into().unwrap()).collect()
+    }
+
+    /// 写一个只有清单和指定 blob 的归档
+    fn write_archive(path: &Path, manifest: &Manifest, blobs: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (hash, data) in blobs {
+            zip.start_file(blob_name(hash), FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.start_file(MANIFEST_NAME, FileOptions::default()).unwrap();
+        zip.write_all(serde_json::to_string(manifest).unwrap().as_bytes()).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trip_keeps_tags_records_and_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let src = StorageConn::open_in_memory().unwrap();
+        let work = src.insert_tag("work".to_string(), None).unwrap();
+        let ops = src.insert_tag("ops".to_string(), Some(work.id)).unwrap();
+        src.insert_record_with_tag(text("first"), work.id).unwrap();
+        let mut secret = text("token");
+        secret.secret = true;
+        secret.time = 42;
+        src.insert_record_with_tag(secret, work.id).unwrap();
+        src.insert_record_with_tag(record(ClipboardFormatContent::Image(vec![1, 2, 3])), ops.id).unwrap();
+        src.insert_record(text("history")).unwrap();
+
+        let manifest = export(&src, &path, None, "1.0.0").unwrap();
+        assert_eq!(manifest.records.len(), 4);
+
+        let dst = StorageConn::open_in_memory().unwrap();
+        let report = import(&dst, &path).unwrap();
+        assert_eq!((report.tags_created, report.records_imported, report.records_skipped), (2, 4, 0));
+        let (work, ops) = (tag_id(&dst, "work"), tag_id(&dst, "ops"));
+        let ops_tag = dst.get_tags().unwrap().into_iter().find(|tag| tag.id == ops).unwrap();
+        assert_eq!(ops_tag.parent_id, Some(work));
+        let records = dst.get_records_with_tag(work).unwrap();
+        assert_eq!(records.iter().map(|r| r.main_data.as_str()).collect::<Vec<_>>(), [r#"{"Text":"first"}"#, r#"{"Text":"token"}"#]);
+        assert!(!records[0].secret && records[1].secret);
+        assert_eq!(records[1].time, 42);
+        assert!(matches!(&contents(&dst, ops)[0].main_format, ClipboardFormatContent::Image(data) if data == &[1, 2, 3]));
+        assert_eq!(contents(&dst, 0).len(), 1);
+    }
+
+    #[test]
+    fn reimport_skips_existing_records_and_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let db = StorageConn::open_in_memory().unwrap();
+        let work = db.insert_tag("work".to_string(), None).unwrap();
+        db.insert_record_with_tag(text("a"), work.id).unwrap();
+        db.insert_record_with_tag(text("b"), work.id).unwrap();
+        export(&db, &path, Some(&[work.id]), "1.0.0").unwrap();
+
+        let report = import(&db, &path).unwrap();
+        assert_eq!((report.tags_created, report.records_imported, report.records_skipped), (0, 0, 2));
+        assert_eq!(db.get_tags().unwrap().len(), 2);
+        assert_eq!(db.get_records_with_tag(work.id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn same_content_in_another_tag_is_imported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let src = StorageConn::open_in_memory().unwrap();
+        let snippets = src.insert_tag("Snippets".to_string(), None).unwrap();
+        src.insert_record_with_tag(text("foo"), snippets.id).unwrap();
+        export(&src, &path, None, "1.0.0").unwrap();
+
+        let dst = StorageConn::open_in_memory().unwrap();
+        let work = dst.insert_tag("Work".to_string(), None).unwrap();
+        dst.insert_record_with_tag(text("foo"), work.id).unwrap();
+        let report = import(&dst, &path).unwrap();
+        assert_eq!((report.records_imported, report.records_skipped), (1, 0));
+        assert_eq!(dst.get_records_with_tag(tag_id(&dst, "Snippets")).unwrap().len(), 1);
+        assert_eq!(dst.get_records_with_tag(work.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn exporting_a_subtag_makes_it_top_level() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let db = StorageConn::open_in_memory().unwrap();
+        let work = db.insert_tag("work".to_string(), None).unwrap();
+        let ops = db.insert_tag("ops".to_string(), Some(work.id)).unwrap();
+        db.insert_tag("deploy".to_string(), Some(ops.id)).unwrap();
+        db.insert_record_with_tag(text("not exported"), work.id).unwrap();
+
+        let manifest = export(&db, &path, Some(&[ops.id]), "1.0.0").unwrap();
+        let names: Vec<(&str, Option<i64>)> = manifest.tags.iter().map(|tag| (tag.name.as_str(), tag.parent_id)).collect();
+        assert_eq!(names, [("ops", None), ("deploy", Some(ops.id))]);
+        assert!(manifest.records.is_empty());
+    }
+
+    #[test]
+    fn unsupported_version_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let manifest = Manifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION + 1,
+            app_version: "1.0.0".to_string(),
+            exported_at: 0,
+            tags: vec![],
+            records: vec![],
+        };
+        write_archive(&path, &manifest, &[]);
+        let db = StorageConn::open_in_memory().unwrap();
+        assert!(matches!(import(&db, &path), Err(ArchiveError::Invalid(_))));
+    }
+
+    #[test]
+    fn corrupted_blob_rolls_back_the_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.zip");
+        let hash = content_hash(b"original");
+        let manifest = Manifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            version: ARCHIVE_VERSION,
+            app_version: "1.0.0".to_string(),
+            exported_at: 0,
+            tags: vec![ManifestTag { id: 5, name: "work".to_string(), parent_id: None, color: None, icon: None }],
+            records: vec![ManifestRecord { tag_id: 5, content_type: "text".to_string(), time: 0, hash: hash.clone(), secret: false }],
+        };
+        write_archive(&path, &manifest, &[(&hash, b"tampered")]);
+        let db = StorageConn::open_in_memory().unwrap();
+        let err = import(&db, &path).unwrap_err();
+        assert_eq!(err.to_string(), format!("blob {} is corrupted", hash));
+        // 标签也在同一个事务中，不会留下
+        assert_eq!(db.get_tags().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,307 @@
+use std::{fs, io::{self, Read}, path::{Path, PathBuf}};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::{Config, PackageInfo};
+
+use crate::{archive, storage::{StorageConn, ClipboardRecord}, clipboard::{ClipboardManager, ClipboardContent, ClipboardFormatContent}};
+
+const USAGE: &str = "\
+Usage:
+    drawer list [--tag <id|name>] [--limit <n>] [--json]
+    drawer get <id> [--json]
+    drawer copy <id>
+    drawer add [--tag <id|name>] [--json] < file
+    drawer export <file> [--tag <id>]...
+    drawer import <file>
+    drawer --version
+
+Records are read from the history (tag 0) when --tag is omitted.
+copy and add go through the running app when there is one.";
+
+/// 列表中预览文本的最大长度
+const PREVIEW_LEN: usize = 80;
+
+/// 命令行输出的记录
+#[derive(Serialize)]
+struct RecordOutput {
+    id: i64,
+    #[serde(rename = "type")]
+    content_type: String,
+    time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// 图片没有文本内容
+    text: Option<String>,
+}
+
+impl From<ClipboardRecord> for RecordOutput {
+    fn from(record: ClipboardRecord) -> Self {
+        let text = match serde_json::from_str::<ClipboardFormatContent>(&record.main_data) {
+            Ok(ClipboardFormatContent::Text(text)) => Some(text),
+            Ok(ClipboardFormatContent::RTF(rtf)) => Some(String::from_utf8_lossy(&rtf).to_string()),
+            Ok(ClipboardFormatContent::Files(files)) => Some(files.join("\n")),
+            Ok(ClipboardFormatContent::Image(_)) | Err(_) => None,
+        };
+        RecordOutput {
+            id: record.id,
+            content_type: record.content_type,
+            time: record.time,
+            name: record.name,
+            text,
+        }
+    }
+}
+
+impl RecordOutput {
+    /// 单行的预览，用于 plain 格式的列表
+    fn preview(&self) -> String {
+        let text = match (&self.name, &self.text) {
+            (Some(name), _) => name.clone(),
+            (None, Some(text)) => text.split_whitespace().collect::<Vec<&str>>().join(" "),
+            (None, None) => format!("<{}>", self.content_type),
+        };
+        if text.chars().count() > PREVIEW_LEN {
+            format!("{}…", text.chars().take(PREVIEW_LEN).collect::<String>())
+        } else {
+            text
+        }
+    }
+}
+
+/// 命令行参数中的选项，不认识的参数作为位置参数
+#[derive(Default)]
+struct Options {
+    tag: Option<String>,
+    limit: Option<usize>,
+    json: bool,
+    positional: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--tag" => options.tag = Some(iter.next().ok_or_else(|| USAGE.to_string())?.clone()),
+                "--limit" => {
+                    let limit = iter.next().ok_or_else(|| USAGE.to_string())?;
+                    options.limit = Some(limit.parse().map_err(|_| format!("invalid limit: {}", limit))?);
+                }
+                "--json" => options.json = true,
+                _ => options.positional.push(arg.clone()),
+            }
+        }
+        Ok(options)
+    }
+
+    /// 唯一的位置参数，解析为记录 id
+    fn record_id(&self) -> Result<i64, String> {
+        match self.positional.as_slice() {
+            [id] => id.parse().map_err(|_| format!("invalid record id: {}", id)),
+            _ => Err(USAGE.to_string()),
+        }
+    }
+}
+
+/// 命令行模式入口，第一个参数不是子命令时返回 None 以启动界面，否则返回进程退出码
+///
+/// 系统启动界面时也可能带参数，如 macOS 的 -psn_*、桌面文件的 %U，这些都交给界面
+pub fn run(config: &Config, package_info: &PackageInfo) -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (subcommand, rest) = args.split_first()?;
+    let result = match subcommand.as_str() {
+        "list" => open_storage(config).and_then(|db| list(&db, rest)),
+        "get" => open_storage(config).and_then(|db| get(&db, rest)),
+        "copy" => open_storage(config).and_then(|db| copy(config, &db, rest)),
+        "add" => open_storage(config).and_then(|db| add(config, &db, rest)),
+        "export" => open_storage(config).and_then(|db| export(&db, rest, package_info)),
+        "import" => open_storage(config).and_then(|db| import(&db, rest)),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        "--version" | "-V" => {
+            println!("{} {}", package_info.name, package_info.version);
+            Ok(())
+        }
+        _ => return None,
+    };
+    match result {
+        Ok(()) => Some(0),
+        Err(err) => {
+            eprintln!("{}", err);
+            Some(1)
+        }
+    }
+}
+
+fn data_dir(config: &Config) -> Result<PathBuf, String> {
+    tauri::api::path::app_data_dir(config).ok_or_else(|| "failed to fetch data directory".to_string())
+}
+
+fn open_storage(config: &Config) -> Result<StorageConn, String> {
+    let data_dir = data_dir(config)?;
+    fs::create_dir_all(&data_dir).map_err(|err| err.to_string())?;
+    StorageConn::open(&data_dir).map_err(|err| err.to_string())
+}
+
+/// 通过 IPC socket 让正在运行的界面执行命令，界面没有运行时返回 None
+#[cfg(unix)]
+fn call_app(config: &Config, method: &str, params: Value) -> Result<Option<Value>, String> {
+    use std::{io::{BufRead, BufReader, Write}, os::unix::net::UnixStream, time::Duration};
+
+    let mut stream = match UnixStream::connect(data_dir(config)?.join(crate::ipc::SOCKET_NAME)) {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(10))).map_err(|err| err.to_string())?;
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    writeln!(stream, "{}", request).map_err(|err| err.to_string())?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).map_err(|err| err.to_string())?;
+    let response: Value = serde_json::from_str(&line).map_err(|err| err.to_string())?;
+    match response.get("error") {
+        Some(error) => Err(error["message"].as_str().unwrap_or("ipc error").to_string()),
+        None => Ok(Some(response["result"].clone())),
+    }
+}
+
+#[cfg(not(unix))]
+fn call_app(_config: &Config, _method: &str, _params: Value) -> Result<Option<Value>, String> {
+    Ok(None)
+}
+
+/// 标签可以用 id 或名称指定，没有指定时为剪贴板历史
+fn resolve_tag(db: &StorageConn, tag: Option<&String>) -> Result<i64, String> {
+    let tag = match tag {
+        Some(tag) => tag,
+        None => return Ok(0),
+    };
+    let tags = db.get_tags().map_err(|err| err.to_string())?;
+    let found = match tag.parse::<i64>() {
+        Ok(id) => tags.iter().find(|t| t.id == id),
+        Err(_) => tags.iter().find(|t| &t.name == tag),
+    };
+    found.map(|t| t.id).ok_or_else(|| format!("tag {} not found", tag))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    println!("{}", serde_json::to_string_pretty(value).map_err(|err| err.to_string())?);
+    Ok(())
+}
+
+fn list(db: &StorageConn, args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    if !options.positional.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let tag_id = resolve_tag(db, options.tag.as_ref())?;
+    // 和界面中的顺序一致，最新的在前
+    let mut records = db.get_records_with_tag(tag_id).map_err(|err| err.to_string())?;
+    records.reverse();
+    if let Some(limit) = options.limit {
+        records.truncate(limit);
+    }
+    let records: Vec<RecordOutput> = records.into_iter().map(RecordOutput::from).collect();
+    if options.json {
+        return print_json(&records);
+    }
+    for record in &records {
+        println!("{}\t{}\t{}", record.id, record.content_type, record.preview());
+    }
+    Ok(())
+}
+
+fn get(db: &StorageConn, args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let record: RecordOutput = db.get_record(options.record_id()?).map_err(|err| err.to_string())?.into();
+    if options.json {
+        return print_json(&record);
+    }
+    match &record.text {
+        Some(text) => print!("{}", text),
+        None => println!("<{}>", record.content_type),
+    }
+    Ok(())
+}
+
+/// 界面在运行时交给界面写剪贴板：片段会展开，X11 上剪贴板内容也不会随命令行退出而丢失
+fn copy(config: &Config, db: &StorageConn, args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let id = options.record_id()?;
+    if call_app(config, "copy", json!({ "id": id }))?.is_some() {
+        return Ok(());
+    }
+    let record = db.get_record(id).map_err(|err| err.to_string())?;
+    let content: ClipboardContent = record.try_into().map_err(|err: serde_json::Error| err.to_string())?;
+    ClipboardManager::write(content)
+}
+
+/// 界面在运行时交给界面添加，加到剪贴板历史时界面会立即刷新
+fn add(config: &Config, db: &StorageConn, args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    if !options.positional.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let tag_id = resolve_tag(db, options.tag.as_ref())?;
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text).map_err(|err| err.to_string())?;
+    if text.is_empty() {
+        return Err("nothing to add: stdin is empty".to_string());
+    }
+    let record = match call_app(config, "add", json!({ "tagId": tag_id, "text": text }))? {
+        Some(result) => {
+            let id = result["id"].as_i64().ok_or_else(|| "invalid response from app".to_string())?;
+            db.get_record(id).map_err(|err| err.to_string())?
+        }
+        None => {
+            let content = ClipboardContent {
+                main_format: ClipboardFormatContent::Text(text.clone()),
+                data: vec![ClipboardFormatContent::Text(text)],
+            };
+            let record: ClipboardRecord = content.try_into().map_err(|err: serde_json::Error| err.to_string())?;
+            db.insert_record_with_tag(record, tag_id).map_err(|err| err.to_string())?
+        }
+    };
+    let record: RecordOutput = record.into();
+    if options.json {
+        return print_json(&record);
+    }
+    println!("{}", record.id);
+    Ok(())
+}
+
+fn export(db: &StorageConn, args: &[String], package_info: &PackageInfo) -> Result<(), String> {
+    let mut file = None;
+    let mut tag_ids = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--tag" => {
+                let id = iter.next().ok_or_else(|| USAGE.to_string())?;
+                tag_ids.push(id.parse::<i64>().map_err(|_| format!("invalid tag id: {}", id))?);
+            }
+            _ if file.is_none() => file = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let file = file.ok_or_else(|| USAGE.to_string())?;
+    let tag_ids = if tag_ids.is_empty() { None } else { Some(tag_ids.as_slice()) };
+    let manifest = archive::export(db, Path::new(file), tag_ids, &package_info.version.to_string())
+        .map_err(|err| err.to_string())?;
+    println!("exported {} tags, {} records to {}", manifest.tags.len(), manifest.records.len(), file);
+    Ok(())
+}
+
+fn import(db: &StorageConn, args: &[String]) -> Result<(), String> {
+    let file = match args {
+        [file] => file,
+        _ => return Err(USAGE.to_string()),
+    };
+    let report = archive::import(db, Path::new(file)).map_err(|err| err.to_string())?;
+    println!("created {} tags, imported {} records, skipped {} duplicated records",
+        report.tags_created, report.records_imported, report.records_skipped);
+    Ok(())
+}
@@ -0,0 +1,342 @@
+//! Interactions with the CLIPBOARD selection on X11.
+//!
+//! X11 的剪贴板内容由复制它的程序持有，其他程序粘贴时再向它请求。写入时由后台线程持有内容并响应请求，
+//! 直到其他程序成为选区的所有者；读取时向当前的所有者请求。超过单个请求上限的内容按 INCR 协议分段收发
+use std::{sync::{Arc, Mutex, OnceLock}, thread, time::{Duration, Instant}};
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, CreateWindowAux, EventMask, PropMode, Property, PropertyNotifyEvent, SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+use crate::clipboard::clipboard::{ClipboardFormat, FormatId};
+
+/// 等待选区所有者响应的最长时间，分段接收时每收到一段重新计时
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// 分段发送时每段的最大长度，X server 的请求上限更小时用它的一半
+const MAX_CHUNK_SIZE: usize = 256 * 1024;
+/// 请求方多久没有取走一段就放弃这次分段发送
+const INCR_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Error = Box<dyn std::error::Error>;
+/// 持有的内容 (target, data)，分段发送时和发送中的 Transfer 共用
+type Formats = Vec<(Atom, Arc<[u8]>)>;
+
+#[derive(Debug, Clone, Default)]
+pub struct Clipboard;
+
+impl Clipboard {
+    /// Put a string onto the system clipboard.
+    pub fn put_string(&mut self, s: impl AsRef<str>) {
+        self.put_formats(&[ClipboardFormat::from(s.as_ref())]);
+    }
+
+    /// Put multi-format data on the system clipboard.
+    pub fn put_formats(&mut self, formats: &[ClipboardFormat]) {
+        if let Some(context) = context() {
+            if let Err(err) = context.store(formats) {
+                log::error!("failed to set clipboard: {}", err);
+            }
+        }
+    }
+
+    /// Get a string from the system clipboard, if one is available.
+    pub fn get_string(&self) -> Option<String> {
+        let context = context()?;
+        String::from_utf8(context.load(context.atoms.utf8_string)?).ok()
+    }
+
+    /// Given a list of supported clipboard types, returns the supported type which has
+    /// highest priority on the system clipboard, or `None` if no types are supported.
+    pub fn preferred_format(&self, formats: &[FormatId]) -> Option<FormatId> {
+        let available = self.available_type_names();
+        formats.iter().find(|format| available.iter().any(|name| name == *format)).copied()
+    }
+
+    /// Return data in a given format, if available.
+    pub fn get_format(&self, fmt: FormatId) -> Option<Vec<u8>> {
+        let context = context()?;
+        let target = context.reader.lock().unwrap().atom(fmt)?;
+        context.load(target)
+    }
+
+    pub fn available_type_names(&self) -> Vec<String> {
+        let context = match context() {
+            Some(context) => context,
+            None => return vec![],
+        };
+        let targets = context.load(context.atoms.targets).unwrap_or_default();
+        let reader = context.reader.lock().unwrap();
+        targets.chunks_exact(4)
+            .map(|atom| u32::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]]))
+            .filter_map(|atom| reader.atom_name(atom))
+            .collect()
+    }
+}
+
+/// 一个到 X server 的连接和用来收发选区的隐藏窗口
+struct Endpoint {
+    conn: RustConnection,
+    window: Window,
+}
+
+impl Endpoint {
+    fn connect() -> Result<Endpoint, Error> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let window = conn.generate_id()?;
+        let root = conn.setup().roots[screen].root;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT, window, root, 0, 0, 1, 1, 0,
+            WindowClass::INPUT_OUTPUT, COPY_FROM_PARENT,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )?.check()?;
+        Ok(Endpoint { conn, window })
+    }
+
+    fn atom(&self, name: &str) -> Option<Atom> {
+        Some(self.conn.intern_atom(false, name.as_bytes()).ok()?.reply().ok()?.atom)
+    }
+
+    fn atom_name(&self, atom: Atom) -> Option<String> {
+        let reply = self.conn.get_atom_name(atom).ok()?.reply().ok()?;
+        Some(String::from_utf8_lossy(&reply.name).to_string())
+    }
+
+    /// 等待满足 f 的事件，超时返回 None
+    fn wait_for<T>(&self, deadline: Instant, mut f: impl FnMut(Event) -> Option<T>) -> Option<T> {
+        loop {
+            match self.conn.poll_for_event().ok()? {
+                Some(event) => if let Some(ret) = f(event) {
+                    return Some(ret);
+                },
+                None if Instant::now() > deadline => return None,
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Atoms {
+    clipboard: Atom,
+    targets: Atom,
+    utf8_string: Atom,
+    incr: Atom,
+    /// 读取时让所有者把内容写到这个属性上
+    property: Atom,
+}
+
+/// 读取和持有内容各用一个连接，持有内容的连接由后台线程响应其他程序的请求
+struct Context {
+    reader: Mutex<Endpoint>,
+    owner: Arc<Endpoint>,
+    /// 当前持有的内容，其他程序成为所有者后清空
+    formats: Arc<Mutex<Formats>>,
+    atoms: Atoms,
+}
+
+/// 按 INCR 协议分段发送给一个请求方的内容
+struct Transfer {
+    requestor: Window,
+    property: Atom,
+    target: Atom,
+    data: Arc<[u8]>,
+    /// 已经写入的长度
+    sent: usize,
+    /// 上次写入的时间，超时的发送在下次开始分段发送时清理
+    updated: Instant,
+}
+
+/// 第一次使用时连接 X server，连接不上（如没有 XWayland 的 Wayland 会话）时剪贴板不可用
+fn context() -> Option<&'static Context> {
+    static CONTEXT: OnceLock<Option<Context>> = OnceLock::new();
+    CONTEXT.get_or_init(|| match Context::new() {
+        Ok(context) => Some(context),
+        Err(err) => {
+            log::error!("failed to connect to X server: {}", err);
+            None
+        }
+    }).as_ref()
+}
+
+impl Context {
+    fn new() -> Result<Context, Error> {
+        let reader = Endpoint::connect()?;
+        let intern = |name: &str| reader.atom(name).ok_or_else(|| format!("failed to intern atom {}", name));
+        let atoms = Atoms {
+            clipboard: intern("CLIPBOARD")?,
+            targets: intern("TARGETS")?,
+            utf8_string: intern("UTF8_STRING")?,
+            incr: intern("INCR")?,
+            property: intern("DRAWER_SELECTION")?,
+        };
+        let owner = Arc::new(Endpoint::connect()?);
+        let formats = Arc::new(Mutex::new(Vec::new()));
+        {
+            let (owner, formats) = (owner.clone(), formats.clone());
+            thread::spawn(move || Self::serve(&owner, &formats, atoms));
+        }
+        Ok(Context { reader: Mutex::new(reader), owner, formats, atoms })
+    }
+
+    /// 持有 formats 并成为 CLIPBOARD 的所有者
+    fn store(&self, formats: &[ClipboardFormat]) -> Result<(), Error> {
+        let mut stored = Vec::new();
+        for format in formats {
+            let target = self.owner.atom(format.identifier).ok_or_else(|| format!("failed to intern atom {}", format.identifier))?;
+            stored.push((target, Arc::from(format.data.as_slice())));
+        }
+        *self.formats.lock().unwrap() = stored;
+        self.owner.conn.set_selection_owner(self.owner.window, self.atoms.clipboard, CURRENT_TIME)?.check()?;
+        let owner = self.owner.conn.get_selection_owner(self.atoms.clipboard)?.reply()?.owner;
+        if owner != self.owner.window {
+            return Err("failed to become the clipboard owner".into());
+        }
+        Ok(())
+    }
+
+    /// 响应其他程序对持有内容的请求
+    fn serve(owner: &Endpoint, formats: &Mutex<Formats>, atoms: Atoms) {
+        let chunk_size = (owner.conn.maximum_request_bytes() / 2).min(MAX_CHUNK_SIZE);
+        let mut transfers: Vec<Transfer> = Vec::new();
+        loop {
+            let event = match owner.conn.wait_for_event() {
+                Ok(event) => event,
+                Err(err) => {
+                    log::error!("clipboard owner lost connection to X server: {}", err);
+                    return;
+                }
+            };
+            let result = match event {
+                Event::SelectionRequest(request) => {
+                    let formats = formats.lock().unwrap().clone();
+                    Self::answer(owner, &formats, atoms, chunk_size, &request, &mut transfers)
+                }
+                // 请求方每取走一段，就写入下一段
+                Event::PropertyNotify(event) if event.state == Property::DELETE => {
+                    Self::send_chunk(owner, chunk_size, &event, &mut transfers)
+                }
+                Event::SelectionClear(_) => {
+                    formats.lock().unwrap().clear();
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(err) = result.and_then(|_| owner.conn.flush().map_err(Error::from)) {
+                log::warn!("failed to answer clipboard request: {}", err);
+            }
+        }
+    }
+
+    /// 写入请求的内容并通知请求方，没有该格式或写入失败时通知的属性为 NONE
+    fn answer(owner: &Endpoint, formats: &Formats, atoms: Atoms, chunk_size: usize, request: &SelectionRequestEvent, transfers: &mut Vec<Transfer>) -> Result<(), Error> {
+        let property = Self::reply(owner, formats, atoms, chunk_size, request, transfers).unwrap_or_else(|err| {
+            log::warn!("failed to send clipboard data: {}", err);
+            NONE
+        });
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property,
+        };
+        owner.conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)?;
+        Ok(())
+    }
+
+    /// 把请求的格式写到请求方的属性上，返回写入的属性，没有该格式时返回 NONE
+    ///
+    /// 超过 chunk_size 的内容先写入 INCR 和总长度，之后由 send_chunk 分段写入
+    fn reply(owner: &Endpoint, formats: &Formats, atoms: Atoms, chunk_size: usize, request: &SelectionRequestEvent, transfers: &mut Vec<Transfer>) -> Result<Atom, Error> {
+        // 旧的客户端可能不指定属性，按规范用 target 代替
+        let property = if request.property == NONE { request.target } else { request.property };
+        if request.target == atoms.targets {
+            let mut targets = vec![atoms.targets];
+            targets.extend(formats.iter().map(|(target, _)| *target));
+            owner.conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &targets)?;
+            return Ok(property);
+        }
+        let (target, data) = match formats.iter().find(|(target, _)| *target == request.target) {
+            Some(format) => format,
+            None => return Ok(NONE),
+        };
+        if data.len() <= chunk_size {
+            owner.conn.change_property8(PropMode::REPLACE, request.requestor, property, *target, data)?;
+            return Ok(property);
+        }
+        // 监听请求方窗口的属性变化，才能知道它什么时候取走了一段
+        let events = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+        owner.conn.change_window_attributes(request.requestor, &events)?;
+        owner.conn.change_property32(PropMode::REPLACE, request.requestor, property, atoms.incr, &[data.len() as u32])?;
+        transfers.retain(|transfer| {
+            transfer.updated.elapsed() < INCR_TIMEOUT && (transfer.requestor, transfer.property) != (request.requestor, property)
+        });
+        transfers.push(Transfer {
+            requestor: request.requestor,
+            property,
+            target: *target,
+            data: data.clone(),
+            sent: 0,
+            updated: Instant::now(),
+        });
+        Ok(property)
+    }
+
+    /// 请求方删除了属性，写入下一段，全部写完后写入空内容表示结束
+    fn send_chunk(owner: &Endpoint, chunk_size: usize, event: &PropertyNotifyEvent, transfers: &mut Vec<Transfer>) -> Result<(), Error> {
+        let i = match transfers.iter().position(|transfer| (transfer.requestor, transfer.property) == (event.window, event.atom)) {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        let transfer = &mut transfers[i];
+        let end = (transfer.sent + chunk_size).min(transfer.data.len());
+        owner.conn.change_property8(PropMode::REPLACE, transfer.requestor, transfer.property, transfer.target, &transfer.data[transfer.sent..end])?;
+        if transfer.sent < end {
+            transfer.sent = end;
+            transfer.updated = Instant::now();
+            return Ok(());
+        }
+        let transfer = transfers.remove(i);
+        owner.conn.change_window_attributes(transfer.requestor, &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT))?;
+        Ok(())
+    }
+
+    /// 向 CLIPBOARD 的所有者请求 target 格式的内容
+    fn load(&self, target: Atom) -> Option<Vec<u8>> {
+        let reader = self.reader.lock().unwrap();
+        let (conn, window, property) = (&reader.conn, reader.window, self.atoms.property);
+        conn.convert_selection(window, self.atoms.clipboard, target, property, CURRENT_TIME).ok()?;
+        conn.flush().ok()?;
+        let converted = reader.wait_for(Instant::now() + LOAD_TIMEOUT, |event| match event {
+            Event::SelectionNotify(event) if event.requestor == window && event.target == target => Some(event.property != NONE),
+            _ => None,
+        })?;
+        if !converted {
+            return None;
+        }
+
+        let reply = conn.get_property(true, window, property, AtomEnum::ANY, 0, u32::MAX).ok()?.reply().ok()?;
+        if reply.type_ != self.atoms.incr {
+            return Some(reply.value);
+        }
+        // 删除 INCR 属性后所有者开始分段写入，每读走一段再写下一段，写入空内容表示结束
+        let mut data = Vec::new();
+        loop {
+            reader.wait_for(Instant::now() + LOAD_TIMEOUT, |event| match event {
+                Event::PropertyNotify(event) if event.atom == property && event.state == Property::NEW_VALUE => Some(()),
+                _ => None,
+            })?;
+            let chunk = conn.get_property(true, window, property, AtomEnum::ANY, 0, u32::MAX).ok()?.reply().ok()?;
+            if chunk.value.is_empty() {
+                return Some(data);
+            }
+            data.extend(chunk.value);
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use std::{thread, sync::{Arc, Mutex, mpsc::{Sender, Receiver, self}}};
+use std::{thread, panic::{self, AssertUnwindSafe}, time::{Duration, Instant}, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}, mpsc::{Sender, Receiver, self}}};
 
+use chrono::Local;
 use clipboard_master::{Master, ClipboardHandler};
-use serde::{Serialize, Deserialize};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use urlencoding::decode_binary;
-use crate::{clipboard::clipboard::ClipboardFormat};
+use crate::{clipboard::clipboard::{ClipboardFormat, FormatId}, event::{EventSink, Topic}};
+pub use drawer::content::{ClipboardContent, ClipboardFormatContent};
 
 use super::clipboard::{Clipboard, backend};
 
@@ -26,10 +30,32 @@ cfg_if::cfg_if!{
         const MULTI_FILE: &'static str = "NSFilenamesPboardType";
         const PDF: &'static str = "com.adobe.pdf";
         const COLOR: &'static str = "com.apple.cocoa.pasteboard.color";
+
+        /// 粘贴文件时写入的文件列表
+        fn file_list(file: &str) -> Vec<u8> {
+            format!(r#"
+                <?xml version="1.0" encoding="UTF-8"?>
+                <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+                <plist version="1.0">
+                <array>
+                    <string>{}</string>
+                </array>
+                </plist>
+            "#, file).into()
+        }
     } else if #[cfg(target_os = "windows")] {
         // Windows: https://www.codeproject.com/Reference/1091137/Windows-Clipboard-Formats
     } else {
-        // Unix(X11)
+        // Unix(X11): 格式名为 MIME 类型
+        const RTF: &'static str = "text/rtf";
+        const PNG: &'static str = "image/png";
+        const FILE: &'static str = "text/uri-list";
+        const MULTI_FILE: &'static str = "text/uri-list";
+
+        /// 粘贴文件时写入的文件列表
+        fn file_list(file: &str) -> Vec<u8> {
+            format!("file://{}\r\n", file).into()
+        }
     }
 }
 /// 防止重复记录的专属格式
@@ -39,24 +65,109 @@ const PREVENT_FORMAT: ClipboardFormat = ClipboardFormat {
     data: vec![],
 };
 
-/// 不同格式的剪贴板数据
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum ClipboardFormatContent {
-    Text(String),
-    RTF(Vec<u8>),
-    Image(Vec<u8>),
-    Files(Vec<String>),   // ([file_url...])
+/// 监听器出错后第一次重启前的等待时间，之后每次翻倍
+const RESTART_BACKOFF_MIN: Duration = Duration::from_millis(500);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+/// 监听器运行超过这个时间后再出错，等待时间重新计算
+const LISTENER_STABLE: Duration = Duration::from_secs(30);
+/// 发送粘贴按键后等目标应用读完剪贴板再恢复之前的内容
+const RESTORE_DELAY: Duration = Duration::from_millis(500);
+/// 连续的变化事件间隔小于这个时间时合并为一次读取
+const DEBOUNCE: Duration = Duration::from_millis(100);
+/// 持续有变化事件时最多等待这么久就读取一次
+const DEBOUNCE_MAX: Duration = Duration::from_secs(1);
+
+/// 监听器状态
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum ListenerState {
+    Running,
+    /// 出错后等待重启
+    Restarting,
+}
+
+/// 监听器的健康状况，变化时通过 Topic::LISTENER_STATUS 通知
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerStatus {
+    pub state: ListenerState,
+    /// 累计重启次数
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// 进入当前状态的时间
+    pub since: i64,
+    /// 最近一次读取到剪贴板变化的时间
+    pub last_capture: Option<i64>,
+    /// 暂停记录时仍然监听，但不读取剪贴板
+    pub paused: bool,
+}
+
+impl Default for ListenerStatus {
+    fn default() -> Self {
+        ListenerStatus {
+            state: ListenerState::Running,
+            restarts: 0,
+            last_error: None,
+            since: Local::now().timestamp_millis(),
+            last_capture: None,
+            paused: false,
+        }
+    }
+}
+
+/// 粘贴前剪贴板上所有格式的数据，粘贴完成后调用 restore 恢复
+///
+/// 粘贴按键发送失败时直接丢弃，粘贴的内容留在剪贴板上方便手动粘贴
+#[must_use]
+pub struct PendingRestore {
+    inner: Arc<Mutex<InnerManger>>,
+    formats: Vec<ClipboardFormat>,
+}
+
+impl PendingRestore {
+    /// 等目标应用读完剪贴板后恢复，恢复的数据同样带上 PREVENT_RECOPY，不会记录到历史
+    ///
+    /// 如果这期间用户又复制了其他内容（剪贴板上没有 PREVENT_RECOPY 了），就不再恢复
+    pub fn restore(self) {
+        thread::spawn(move || {
+            thread::sleep(RESTORE_DELAY);
+            let mut inner = self.inner.lock().unwrap();
+            if inner.clipboard.get_format(PREVENT_RECOPY).is_none() {
+                log::debug!("clipboard changed after paste, skip restoring");
+                return;
+            }
+            let mut formats = vec![PREVENT_FORMAT];
+            formats.extend(self.formats);
+            inner.clipboard.put_formats(&formats);
+            log::debug!("restored {} clipboard formats", formats.len() - 1);
+        });
+    }
+}
+
+/// get_format 需要 'static 的格式名，剪贴板上出现过的每种格式名只泄漏一次
+fn intern_format(name: String) -> FormatId {
+    static NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+    let mut names = NAMES.lock().unwrap();
+    if let Some(interned) = names.iter().find(|interned| **interned == name) {
+        return interned;
+    }
+    let interned: &'static str = Box::leak(name.into_boxed_str());
+    names.push(interned);
+    interned
 }
 
-/// 剪贴板内容
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct ClipboardContent {
-    pub main_format: ClipboardFormatContent,
-    pub data: Vec<ClipboardFormatContent>
+/// 剪贴板内容的摘要，用于判断剪贴板之后有没有被改动
+fn digest(formats: &[ClipboardFormat]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for format in formats {
+        hasher.update(format.identifier.as_bytes());
+        hasher.update((format.data.len() as u64).to_le_bytes());
+        hasher.update(&format.data);
+    }
+    hasher.finalize().to_vec()
 }
 
-/// 剪贴板管理
-pub struct ClipboardManager (Arc<Mutex<InnerManger>>);
+/// 剪贴板管理，最后一个字段用于让之前安排的清除失效
+pub struct ClipboardManager (Arc<Mutex<InnerManger>>, Arc<Mutex<ListenerStatus>>, Arc<AtomicU64>);
 
 struct InnerManger {
     callback: ClipboardCallback,
@@ -68,25 +179,42 @@ struct Handler {
 }
 
 impl ClipboardManager {
-    pub fn new<F>(callback: F) -> ClipboardManager where F: Fn(ClipboardContent) + Send + 'static {
+    /// sink 用于通知监听器状态的变化
+    pub fn new<F, S>(callback: F, sink: S) -> ClipboardManager
+        where F: Fn(ClipboardContent) + Send + 'static, S: EventSink {
         let (tx, rx) = mpsc::channel();
-        let cm = ClipboardManager ( Arc::new(Mutex::new(InnerManger {
-            callback: Box::new(callback),
-            clipboard: Clipboard(backend::Clipboard)
-        })));
-        cm.listener_launch(rx, tx);
+        let cm = ClipboardManager (
+            Arc::new(Mutex::new(InnerManger {
+                callback: Box::new(callback),
+                clipboard: Clipboard(backend::Clipboard)
+            })),
+            Arc::new(Mutex::new(ListenerStatus::default())),
+            Arc::new(AtomicU64::new(0)),
+        );
+        cm.listener_launch(rx, tx, Arc::new(sink));
         cm
     }
 
     /// 启动剪贴板监听器
-    fn listener_launch(&self, rx: Receiver<()>, tx: Sender<()>) {
-        thread::spawn(move || {
-            Master::new(Handler{tx}).run().unwrap();
-        });
+    fn listener_launch(&self, rx: Receiver<()>, tx: Sender<()>, sink: Arc<dyn EventSink>) {
+        let status = self.1.clone();
+        thread::spawn(move || Self::supervise(tx, &status, sink.as_ref()));
 
         // 收到更新事件后，获取剪贴板内容，调用callback
         let _inner = self.0.clone();
-        thread::spawn(move || for _ in rx {
+        let status = self.1.clone();
+        thread::spawn(move || while rx.recv().is_ok() {
+            // 复制时应用常常连续写入多种格式，等变化停下来再读取
+            let start = Instant::now();
+            while start.elapsed() < DEBOUNCE_MAX && rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+            {
+                let mut status = status.lock().unwrap();
+                if status.paused {
+                    continue;
+                }
+                status.last_capture = Some(Local::now().timestamp_millis());
+            }
             let inner = _inner.lock().unwrap();
             if let Some(content) = inner.get_content() {
                 (*inner.callback)(content);
@@ -94,17 +222,116 @@ impl ClipboardManager {
         });
     }
 
+    /// 运行监听器，出错或 panic 后按指数退避重启
+    fn supervise(tx: Sender<()>, status: &Mutex<ListenerStatus>, sink: &dyn EventSink) {
+        let mut backoff = RESTART_BACKOFF_MIN;
+        loop {
+            let started = Instant::now();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| Master::new(Handler{tx: tx.clone()}).run()));
+            let error = match result {
+                Ok(Ok(())) => "clipboard listener stopped".to_string(),
+                Ok(Err(err)) => err.to_string(),
+                Err(_) => "clipboard listener panicked".to_string(),
+            };
+            if started.elapsed() > LISTENER_STABLE {
+                backoff = RESTART_BACKOFF_MIN;
+            }
+            log::error!("{}, restarting in {:?}", error, backoff);
+            Self::set_status(status, sink, |s| {
+                s.state = ListenerState::Restarting;
+                s.restarts += 1;
+                s.last_error = Some(error);
+            });
+
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+            Self::set_status(status, sink, |s| s.state = ListenerState::Running);
+        }
+    }
+
+    fn set_status(status: &Mutex<ListenerStatus>, sink: &dyn EventSink, update: impl FnOnce(&mut ListenerStatus)) {
+        let snapshot = {
+            let mut status = status.lock().unwrap();
+            update(&mut status);
+            status.since = Local::now().timestamp_millis();
+            status.clone()
+        };
+        sink.emit(Topic::LISTENER_STATUS, json!(snapshot));
+    }
+
+    /// 监听器当前的健康状况
+    pub fn status(&self) -> ListenerStatus {
+        self.1.lock().unwrap().clone()
+    }
+
+    /// 暂停或恢复记录剪贴板，返回新的状态
+    pub fn set_paused(&self, paused: bool) -> ListenerStatus {
+        let mut status = self.1.lock().unwrap();
+        status.paused = paused;
+        status.clone()
+    }
+
+    pub fn toggle_paused(&self) -> ListenerStatus {
+        let paused = !self.status().paused;
+        self.set_paused(paused)
+    }
+
+    /// 获取剪贴板上的文本
+    pub fn get_text(&self) -> Option<String> {
+        self.0.lock().unwrap().clipboard.get_string()
+    }
+
     /// 粘贴一条内容到剪贴板上
     pub fn paste(&self, content: ClipboardContent) -> Result<(), String> {
         self.0.clone().lock().unwrap().paste(content.into())
     }
+
+    /// 保存剪贴板上所有格式的数据后再粘贴，发送粘贴按键后调用返回值的 restore 恢复
+    pub fn paste_with_restore(&self, content: ClipboardContent) -> Result<PendingRestore, String> {
+        let mut inner = self.0.lock().unwrap();
+        let formats = inner.snapshot();
+        inner.paste(content)?;
+        Ok(PendingRestore { inner: self.0.clone(), formats })
+    }
+
+    /// delay 后清空剪贴板，用于粘贴敏感内容之后
+    ///
+    /// 清空前比较剪贴板内容的摘要，用户在这期间复制了其他内容时不会清空；再次安排清除时之前的会失效
+    pub fn clear_later(&self, delay: Duration) {
+        let generation = self.2.fetch_add(1, Ordering::SeqCst) + 1;
+        let expected = digest(&self.0.lock().unwrap().snapshot());
+        let (inner, current) = (self.0.clone(), self.2.clone());
+        thread::spawn(move || {
+            thread::sleep(delay);
+            if current.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            let mut inner = inner.lock().unwrap();
+            if digest(&inner.snapshot()) != expected {
+                log::debug!("clipboard changed since pasting secret, skip clearing");
+                return;
+            }
+            // 只留下 PREVENT_RECOPY，清空后的剪贴板也不会被记录
+            inner.clipboard.put_formats(&[PREVENT_FORMAT]);
+            log::info!("cleared secret from clipboard");
+        });
+    }
+
+    /// 不启动监听，直接把内容写到剪贴板上，供命令行使用
+    pub fn write(content: ClipboardContent) -> Result<(), String> {
+        let mut inner = InnerManger {
+            callback: Box::new(|_| {}),
+            clipboard: Clipboard(backend::Clipboard),
+        };
+        inner.paste(content)
+    }
 }
 
 impl InnerManger {
     /// 获取剪贴板内容
     fn get_content(&self) -> Option<ClipboardContent> {
         let types = self.clipboard.available_type_names();
-        println!("avalibe format type: {:?}", &types);
+        log::debug!("available format types: {:?}", &types);
 
         // 本来想尝试存储所有类型的数据以真正存储剪贴板所有内容，但 get_format 参数需要一个 'static str
         // 看起来是因为通过 FFI 申请的内存没有释放所以才必须传入'static来限制，之后有空也许可以优化一下它的实现
@@ -138,8 +365,11 @@ impl InnerManger {
         if let Some(file_url) = self.clipboard.get_format(FILE) {
             let _binary = decode_binary(file_url.as_slice());
             let _file = ClipboardFormatContent::Files(
+                // X11 上是每行一个的 URI 列表，只取第一个
                 vec![String::from_utf8_lossy(&_binary)
-                    .to_string()
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
                     .replace("file://", "")]
             );
             main_format = Some(_file.clone());
@@ -159,6 +389,17 @@ impl InnerManger {
         })
     }
 
+    /// 剪贴板上所有格式的原始数据，不包括 PREVENT_RECOPY
+    fn snapshot(&self) -> Vec<ClipboardFormat> {
+        self.clipboard.available_type_names().into_iter()
+            .filter(|name| name != PREVENT_RECOPY)
+            .filter_map(|name| {
+                let identifier = intern_format(name);
+                self.clipboard.get_format(identifier).map(|data| ClipboardFormat { identifier, data })
+            })
+            .collect()
+    }
+
     /// 粘贴剪贴板内容
     fn paste(&mut self, content: ClipboardContent) -> Result<(), String> {
         let mut formats = vec![PREVENT_FORMAT];
@@ -186,15 +427,7 @@ impl InnerManger {
                     // TODO: 多文件&多文件夹
                     formats.push(ClipboardFormat {
                         identifier: MULTI_FILE,
-                        data: format!(r#"
-                            <?xml version="1.0" encoding="UTF-8"?>
-                            <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
-                            <plist version="1.0">
-                            <array>
-                                <string>{}</string>
-                            </array>
-                            </plist>
-                        "#, files[0]).into(),
+                        data: file_list(&files[0]),
                     });
                 }
             }
@@ -208,12 +441,13 @@ impl InnerManger {
 impl ClipboardHandler for Handler {
     fn on_clipboard_change(&mut self) -> clipboard_master::CallbackResult {
         if let Err(err) = self.tx.send(()) {
-            println!("failed to send clipboard change event {}", err);
+            log::error!("failed to send clipboard change event {}", err);
         }
         clipboard_master::CallbackResult::Next
     }
 
-    fn on_clipboard_error(&mut self, _: std::io::Error) -> clipboard_master::CallbackResult {
-        clipboard_master::CallbackResult::Next
+    /// 交给 supervise 记录错误并重启
+    fn on_clipboard_error(&mut self, err: std::io::Error) -> clipboard_master::CallbackResult {
+        clipboard_master::CallbackResult::StopWithError(err)
     }
 }
\ No newline at end of file
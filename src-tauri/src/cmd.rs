@@ -1,67 +1,110 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use chrono::Local;
+use serde::Serialize;
 use serde_json::json;
 use tauri::{AppHandle, Manager};
+use ts_rs::TS;
+
+use crate::{archive::{self, ImportReport}, sync::{self, SyncState, SyncServer, SyncReport, SyncPeer}, storage::{StorageConn, ClipboardRecord, TagRecord, BackupInfo}, clipboard::{ClipboardManager, ClipboardContent, ClipboardFormatContent, ListenerStatus}, error::{CmdError, CmdResult, Forbidden}, event::Topic, i18n, logger, paste::{self, Keystroke, PasteMode, PasteState, TypedText}, settings::{Settings, SettingsState}, setup, shortcut::{ShortcutFailure, ShortcutSettings, ShortcutState}, template::{Template, SNIPPET_TYPE}, util::{self, Data}};
+
+/// 返回给前端的剪贴板记录，TypeScript 定义由 ts-rs 在 cargo test 时生成到 src/bindings/
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub struct ClipboardRecordVO {
+    #[ts(type = "number")]
+    pub id: i64,
+    /// 创建时间，毫秒时间戳
+    #[ts(type = "number")]
+    pub time: i64,
+    pub description: String,
+    /// 关联目录中片段的名称
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub name: Option<String>,
+    /// 敏感内容，粘贴后会定时清除剪贴板
+    pub secret: bool,
+    #[serde(flatten)]
+    pub content: RecordContentVO,
+}
 
-use crate::{storage::{StorageConn, ClipboardRecord, TagRecord}, clipboard::{ClipboardManager, ClipboardContent, ClipboardFormatContent}, event::Topic, util::{self, Data}};
+/// 不同类型记录的内容，用 type 字段区分
+#[derive(Serialize, Clone, Debug, TS)]
+#[serde(tag = "type", rename_all = "lowercase")]
+#[ts(export, export_to = "../../src/bindings/")]
+pub enum RecordContentVO {
+    Text { data: String },
+    /// 片段模板，inputs 为粘贴前需要用户输入的字段
+    Snippet { data: String, inputs: Vec<String> },
+    Rtf { data: RtfVO },
+    /// base64 编码的 PNG
+    Image { data: String },
+    /// data 为文件路径，thumbnail 为 base64 编码的缩略图
+    File { data: String, thumbnail: String },
+}
 
-pub type ClipboardRecordVO = serde_json::Value;
-pub type CmdResult<T> = Result<T, String>;
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub struct RtfVO {
+    /// base64 编码的 RTF
+    pub rtf: String,
+    /// 字符数
+    pub text: usize,
+}
 
 impl TryFrom<ClipboardRecord> for ClipboardRecordVO {
-    type Error = String;
+    type Error = CmdError;
 
     fn try_from(record: ClipboardRecord) -> Result<Self, Self::Error> {
-        let id = record.id;
-        let create_at = record.time;
-        let content: ClipboardFormatContent = serde_json::from_str(record.main_data.as_str())
-            .map_err(|err| err.to_string())?;
-        match content {
-            ClipboardFormatContent::Text(text) => Ok(json!({
-                "id": id,
-                "type": "text",
-                "time": create_at,
-                "description": format!("{} 个字符", text.chars().count()),
-                "data": text
-            })),
+        let is_snippet = record.content_type == SNIPPET_TYPE;
+        let content: ClipboardFormatContent = serde_json::from_str(record.main_data.as_str())?;
+        let (description, content) = match content {
+            ClipboardFormatContent::Text(text) if is_snippet => (
+                i18n::t_args("char-count", [("count", text.chars().count().into())]),
+                RecordContentVO::Snippet { inputs: Template::parse(&text).inputs(), data: text },
+            ),
+            ClipboardFormatContent::Text(text) => (
+                i18n::t_args("char-count", [("count", text.chars().count().into())]),
+                RecordContentVO::Text { data: text },
+            ),
             ClipboardFormatContent::RTF(rtf) => {
                 let rtf_str = String::from_utf8_lossy(rtf.as_slice()).to_string();
                 let rtf_char_count = rtf_str.chars().count();
-                Ok(json!({
-                    "id": id,
-                    "type": "rtf",
-                    "time": create_at,
-                    "data": json!({
-                        "rtf": base64::encode(rtf),
-                        "text": rtf_char_count,
-                    }),
-                    "description": format!("{} 个字符", rtf_char_count),
-                }))
+                (
+                    i18n::t_args("char-count", [("count", rtf_char_count.into())]),
+                    RecordContentVO::Rtf { data: RtfVO { rtf: base64::encode(rtf), text: rtf_char_count } },
+                )
             },
             ClipboardFormatContent::Image(data) => {
                 let size = util::ImageUtil::img_size(data.as_slice())
-                    .map_err(|err| err.to_string())?;
-                Ok(json!({
-                    "id": id,
-                    "type": "image",
-                    "time": create_at,
-                    "data": base64::encode(data),
-                    "description": format!("{} × {}", size.width, size.height)
-                }))
+                    .map_err(|err| CmdError::InvalidFormat(format!("{:?}", err)))?;
+                (
+                    format!("{} × {}", size.width, size.height),
+                    RecordContentVO::Image { data: base64::encode(data) },
+                )
             },
-            ClipboardFormatContent::Files(file_urls) => Ok(json!({
-                "id": id,
-                "type": "file",
-                "time": create_at,
-                "data": file_urls[0],
-                "description": file_urls[0],
-                "thumbnail": match util::QuickLook::thumbnail(&(file_urls[0])) {
-                    Ok(data) => match data {
-                        Data::Raw(bytes) => base64::encode(bytes),
-                        Data::Base64(base64_str) => base64_str
+            ClipboardFormatContent::Files(file_urls) => (
+                file_urls[0].clone(),
+                RecordContentVO::File {
+                    thumbnail: match util::QuickLook::thumbnail(&(file_urls[0])) {
+                        Ok(data) => match data {
+                            Data::Raw(bytes) => base64::encode(bytes),
+                            Data::Base64(base64_str) => base64_str
+                        },
+                        Err(_) => "".to_string()
                     },
-                    Err(_) => "".to_string()
-                }
-            })),
-        }      
+                    data: file_urls[0].clone(),
+                },
+            ),
+        };
+        Ok(ClipboardRecordVO {
+            id: record.id,
+            time: record.time,
+            description,
+            name: record.name,
+            secret: record.secret,
+            content,
+        })
     }
 }
 
@@ -69,83 +112,593 @@ impl TryFrom<ClipboardRecord> for ClipboardRecordVO {
 #[tauri::command]
 pub fn get_all_tags(app: AppHandle) -> CmdResult<Vec<TagRecord>> {
     let db = app.state::<StorageConn>();
-    db.inner().get_tags().map_err(|err| err.to_string())
+    db.inner().get_tags().map_err(CmdError::from)
 }
 
-/// 获取指定标签下所有剪贴板记录
+/// 获取某个标签的所有子孙标签
 #[tauri::command]
-pub fn get_all_record(app: AppHandle, tag_id: i64) -> CmdResult<Vec<ClipboardRecordVO>> {
+pub fn get_descendant_tags(app: AppHandle, id: i64) -> CmdResult<Vec<TagRecord>> {
     let db = app.state::<StorageConn>();
-    match db.inner().get_records_with_tag(tag_id) {
-        Ok(records) => {
-            let mut ret = Vec::with_capacity(records.len());
-            for record in records {
-                match TryInto::<ClipboardRecordVO>::try_into(record) {
-                    Ok(vo) => ret.push(vo),
-                    Err(err) => return Err(err.to_string())
-                }
-            }
-            Ok(ret)
-        }
-        Err(err) => Err(err.to_string())
+    db.inner().get_descendant_tags(id).map_err(CmdError::from)
+}
+
+/// 获取指定标签下所有剪贴板记录，include_descendants 为 true 时包含子孙标签下的记录
+#[tauri::command]
+pub fn get_all_record(app: AppHandle, tag_id: i64, include_descendants: Option<bool>) -> CmdResult<Vec<ClipboardRecordVO>> {
+    let db = app.state::<StorageConn>();
+    let records = if include_descendants.unwrap_or(false) {
+        db.inner().get_records_with_tag_recursive(tag_id)
+    } else {
+        db.inner().get_records_with_tag(tag_id)
+    };
+    let mut ret = Vec::new();
+    for record in records? {
+        ret.push(record.try_into()?);
     }
+    Ok(ret)
+}
+
+/// 按文本搜索剪贴板记录
+#[tauri::command]
+pub fn search_records(app: AppHandle, query: String, tag_id: Option<i64>, limit: Option<usize>) -> CmdResult<Vec<ClipboardRecordVO>> {
+    let db = app.state::<StorageConn>();
+    let records = db.inner().search_records(&query, tag_id, limit.unwrap_or(50)).map_err(CmdError::from)?;
+    records.into_iter().map(TryInto::<ClipboardRecordVO>::try_into).collect()
 }
 
 /// 删除剪贴板记录
 #[tauri::command]
-pub fn delete_record(app: AppHandle, id: i64) -> Result<(), String> {
+pub fn delete_record(app: AppHandle, id: i64) -> CmdResult<()> {
     let db = app.state::<StorageConn>();
-    match db.inner().delete_record(id) {
-        Ok(()) => {
-            app.emit_all(Topic::CLIPBOARD_DELETE, vec![id]).unwrap();
-            Ok(())
-        }
-        Err(err) => Err(err.to_string())
-    }
+    db.inner().delete_record(id)?;
+    // 记录已经删除，通知失败只记录日志
+    setup::emit_event(&app, Topic::CLIPBOARD_DELETE, json!([id]));
+    Ok(())
 }
 
 /// 将一条剪贴板记录钉到标签上
 #[tauri::command]
 pub fn pin_record(app: AppHandle, record_id: i64, tag_id: i64) -> CmdResult<()> {
     let db = app.state::<StorageConn>();
-    println!("pin {} to {}", record_id, tag_id);
-    db.inner().copy_record_to_tag(record_id, tag_id).map_err(|err| err.to_string())
+    log::info!("pin {} to {}", record_id, tag_id);
+    db.inner().copy_record_to_tag(record_id, tag_id).map_err(CmdError::from)
+}
+
+/// 调整标签内一条记录的位置，用于拖拽排序，new_index 是记录在界面列表（最新的在前）中的下标
+#[tauri::command]
+pub fn move_record(app: AppHandle, tag_id: i64, record_id: i64, new_index: usize) -> CmdResult<()> {
+    if tag_id == 0 {
+        return Err(Forbidden::ReorderHistory.into());
+    }
+    let db = app.state::<StorageConn>();
+    db.inner().move_record(tag_id, record_id, new_index).map_err(CmdError::from)
 }
 
 /// 创建标签
 #[tauri::command]
-pub fn create_tag(app: AppHandle, name: &str) -> CmdResult<TagRecord> {
+pub fn create_tag(app: AppHandle, name: &str, parent_id: Option<i64>) -> CmdResult<TagRecord> {
+    if parent_id == Some(0) {
+        return Err(Forbidden::NestUnderHistory.into());
+    }
+    let db = app.state::<StorageConn>();
+    db.inner().insert_tag(name.to_string(), parent_id).map_err(CmdError::from)
+}
+
+/// 移动标签到另一个标签下，parent_id 为空时移动到顶层
+#[tauri::command]
+pub fn move_tag(app: AppHandle, id: i64, parent_id: Option<i64>) -> CmdResult<TagRecord> {
+    if id == 0 {
+        return Err(Forbidden::MoveHistory.into());
+    }
+    if parent_id == Some(0) {
+        return Err(Forbidden::NestUnderHistory.into());
+    }
+    let db = app.state::<StorageConn>();
+    db.inner().move_tag(id, parent_id).map_err(CmdError::from)
+}
+
+/// 修改标签的名称、颜色或图标
+#[tauri::command]
+pub fn update_tag(app: AppHandle, id: i64, name: Option<String>, color: Option<String>, icon: Option<String>) -> CmdResult<TagRecord> {
+    let db = app.state::<StorageConn>();
+    db.inner().update_tag(id, name, color, icon).map_err(CmdError::from)
+}
+
+/// 把标签关联到一个目录，dir 为空时取消关联
+#[tauri::command]
+pub fn set_tag_linked_dir(app: AppHandle, id: i64, dir: Option<String>) -> CmdResult<TagRecord> {
+    if id == 0 {
+        return Err(Forbidden::LinkHistory.into());
+    }
+    let dir = dir.filter(|dir| !dir.is_empty());
+    let db = app.state::<StorageConn>();
+    db.inner().set_tag_linked_dir(id, dir).map_err(CmdError::from)
+}
+
+/// 调整标签顺序
+#[tauri::command]
+pub fn reorder_tags(app: AppHandle, ids: Vec<i64>) -> CmdResult<Vec<TagRecord>> {
     let db = app.state::<StorageConn>();
-    db.inner().insert_tag(name.to_string()).map_err(|err| err.to_string())
+    db.inner().reorder_tags(&ids).map_err(CmdError::from)?;
+    db.inner().get_tags().map_err(CmdError::from)
 }
 
 /// 删除标签
 #[tauri::command]
 pub fn delete_tag(app: AppHandle, id: i64) -> CmdResult<()> {
     if id == 0 {
-        return Err("剪贴板历史不能删除".to_string());
+        return Err(Forbidden::DeleteHistory.into());
     }
     let db = app.state::<StorageConn>();
-    db.inner().delete_tag(id).map_err(|err| err.to_string())
+    db.inner().delete_tag(id).map_err(CmdError::from)
+}
+
+/// 导出标签下的记录到归档文件，tag_ids 为空时导出全部，返回导出的记录数
+#[tauri::command]
+pub fn export_archive(app: AppHandle, path: String, tag_ids: Option<Vec<i64>>) -> CmdResult<usize> {
+    let db = app.state::<StorageConn>();
+    let version = app.package_info().version.to_string();
+    archive::export(db.inner(), Path::new(&path), tag_ids.as_deref(), &version)
+        .map(|manifest| manifest.records.len())
+        .map_err(CmdError::from)
+}
+
+/// 从归档文件导入，合并到当前数据库
+#[tauri::command]
+pub fn import_archive(app: AppHandle, path: String) -> CmdResult<ImportReport> {
+    let db = app.state::<StorageConn>();
+    archive::import(db.inner(), Path::new(&path)).map_err(CmdError::from)
+}
+
+/// 获取数据库的所有备份，从新到旧排列
+#[tauri::command]
+pub fn list_backups(app: AppHandle) -> CmdResult<Vec<BackupInfo>> {
+    let db = app.state::<StorageConn>();
+    db.inner().list_backups().map_err(CmdError::from)
+}
+
+/// 立即备份数据库
+#[tauri::command]
+pub fn create_backup(app: AppHandle) -> CmdResult<BackupInfo> {
+    let db = app.state::<StorageConn>();
+    db.inner().backup().map_err(CmdError::from)
+}
+
+/// 从备份恢复数据库
+#[tauri::command]
+pub fn restore_backup(app: AppHandle, name: String) -> CmdResult<()> {
+    let db = app.state::<StorageConn>();
+    db.inner().restore_backup(&name).map_err(CmdError::from)
+}
+
+/// 启动同步服务，等待其他设备连入，返回监听的端口
+#[tauri::command]
+pub fn start_sync_server(app: AppHandle, port: Option<u16>, secret: String) -> CmdResult<u16> {
+    sync::check_secret(&secret).map_err(CmdError::from)?;
+    let db = app.state::<StorageConn>();
+    let node = db.inner().sync_node().map_err(CmdError::from)?;
+    // 先停掉已经在运行的服务，释放端口
+    app.state::<SyncState>().inner().0.lock().unwrap().take();
+    let app_handler = app.app_handle();
+    let server = SyncServer::start(&node, port.unwrap_or(sync::SYNC_PORT), move |stream| {
+        let db = app_handler.state::<StorageConn>();
+        match sync::serve(db.inner(), stream, &secret) {
+            Ok(report) => if let Err(err) = app_handler.emit_all(Topic::SYNC_COMPLETE, report) {
+                log::error!("failed to emit sync report: {}", err);
+            },
+            Err(err) => log::warn!("failed to serve sync connection: {}", err),
+        }
+    }).map_err(CmdError::from)?;
+    let port = server.port();
+    *app.state::<SyncState>().inner().0.lock().unwrap() = Some(server);
+    Ok(port)
+}
+
+/// 停止同步服务
+#[tauri::command]
+pub fn stop_sync_server(app: AppHandle) -> CmdResult<()> {
+    app.state::<SyncState>().inner().0.lock().unwrap().take();
+    Ok(())
+}
+
+/// 与另一台设备同步标签，addr 为 ip[:port]
+#[tauri::command]
+pub async fn sync_with_peer(app: AppHandle, addr: String, secret: String) -> CmdResult<SyncReport> {
+    // 网络读写会阻塞，放到阻塞线程池中执行
+    let app_handler = app.app_handle();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        sync::sync_with(app_handler.state::<StorageConn>().inner(), &addr, &secret)
+    }).await?.map_err(CmdError::from)?;
+    app.emit_all(Topic::SYNC_COMPLETE, report.clone()).map_err(CmdError::from)?;
+    Ok(report)
+}
+
+/// 通过 mDNS 查找局域网内可以同步的设备
+#[tauri::command]
+pub async fn discover_sync_peers(app: AppHandle, timeout_ms: Option<u64>) -> CmdResult<Vec<SyncPeer>> {
+    let db = app.state::<StorageConn>();
+    let node = db.inner().sync_node().map_err(CmdError::from)?;
+    let timeout = Duration::from_millis(timeout_ms.unwrap_or(3000));
+    tauri::async_runtime::spawn_blocking(move || sync::discover(&node, timeout)).await?.map_err(CmdError::from)
+}
+
+/// 粘贴命令的结果
+#[derive(Serialize, Clone, Debug, TS)]
+#[serde(tag = "status", rename_all = "camelCase")]
+#[ts(export, export_to = "../../src/bindings/")]
+pub enum PasteResult {
+    /// 只放到了剪贴板上
+    Copied,
+    /// 已经发送粘贴按键
+    Pasted(Keystroke),
+    /// 通过模拟键盘输入了文本
+    Typed(TypedText),
+    /// 片段还有需要用户输入的字段，输入后通过 paste_snippet 粘贴
+    WaitingForInput,
+}
+
+/// 粘贴一条剪贴板记录的内容，mode 为空时使用设置的默认方式
+#[tauri::command]
+pub fn paste(app: AppHandle, id: i64, mode: Option<PasteMode>) -> CmdResult<PasteResult> {
+    let mode = mode.unwrap_or_else(|| app.state::<PasteState>().mode());
+    paste_record(&app, id, mode)
+}
+
+/// 只把记录放到剪贴板上，不隐藏窗口也不发送粘贴按键
+#[tauri::command]
+pub fn copy_record(app: AppHandle, id: i64) -> CmdResult<PasteResult> {
+    paste_record(&app, id, PasteMode::CopyOnly)
+}
+
+#[tauri::command]
+pub fn get_paste_mode(app: AppHandle) -> CmdResult<PasteMode> {
+    Ok(app.state::<PasteState>().mode())
+}
+
+/// 设置默认的粘贴方式
+#[tauri::command]
+pub fn set_paste_mode(app: AppHandle, mode: PasteMode) -> CmdResult<()> {
+    modify_settings(&app, |settings| settings.paste_mode = mode)
 }
 
-/// 粘贴一条剪贴板记录的内容
+/// 标记记录为敏感内容，粘贴后会定时清空剪贴板
 #[tauri::command]
-pub fn paste(app: AppHandle, id: i64) -> CmdResult<String> {
+pub fn set_record_secret(app: AppHandle, id: i64, secret: bool) -> CmdResult<()> {
     let db = app.state::<StorageConn>();
+    db.inner().set_record_secret(id, secret).map_err(CmdError::from)
+}
+
+/// 设置粘贴敏感内容后多少秒清空剪贴板，为 0 时不清空
+#[tauri::command]
+pub fn set_secret_clear_delay(app: AppHandle, seconds: u64) -> CmdResult<()> {
+    modify_settings(&app, |settings| settings.secret_clear_delay = seconds)
+}
+
+/// 带上用户的输入粘贴片段，是 SNIPPET_INPUT 事件的响应
+#[tauri::command]
+pub fn paste_snippet(app: AppHandle, id: i64, inputs: HashMap<String, String>, mode: Option<PasteMode>) -> CmdResult<PasteResult> {
+    let db = app.state::<StorageConn>();
+    let record = db.inner().get_record(id).map_err(CmdError::from)?;
+    if record.content_type != SNIPPET_TYPE {
+        return Err(Forbidden::NotSnippet.into());
+    }
+    let mode = mode.unwrap_or_else(|| app.state::<PasteState>().mode());
+    paste_snippet_record(&app, record, inputs, mode)
+}
+
+/// 模拟键盘输入时默认的字符间隔
+const TYPE_DELAY_MS: u64 = 10;
+/// 字符间隔的上限
+const MAX_TYPE_DELAY_MS: u64 = 1000;
+/// 一次最多输入的字符数，更长的文本应该粘贴
+const MAX_TYPE_CHARS: usize = 5000;
+
+/// 把文本记录当作键盘输入逐个字符发送，用于禁止粘贴的输入框，不会改动剪贴板
+///
+/// 一个字符都没有输入时退回为复制到剪贴板
+#[tauri::command]
+pub async fn type_record(app: AppHandle, id: i64, delay_ms: Option<u64>) -> CmdResult<PasteResult> {
+    // 逐个字符输入可能要很久，放到阻塞线程池中执行
+    tauri::async_runtime::spawn_blocking(move || type_record_blocking(&app, id, delay_ms)).await?
+}
+
+/// 在当前线程中模拟输入，IPC 的请求本来就在后台线程中处理，直接调用这个
+pub fn type_record_blocking(app: &AppHandle, id: i64, delay_ms: Option<u64>) -> CmdResult<PasteResult> {
+    let delay_ms = delay_ms.unwrap_or(TYPE_DELAY_MS);
+    if delay_ms > MAX_TYPE_DELAY_MS {
+        return Err(CmdError::InvalidArgument(format!("delay_ms must not exceed {}", MAX_TYPE_DELAY_MS)));
+    }
+    let db = app.state::<StorageConn>();
+    let record = db.inner().get_record(id)?;
+    let (is_snippet, secret) = (record.content_type == SNIPPET_TYPE, record.secret);
+    let content = ClipboardContent::try_from(record)?;
+    let text = match &content.main_format {
+        ClipboardFormatContent::Text(text) if is_snippet => {
+            let clipboard = app.state::<ClipboardManager>();
+            Template::parse(text).expand(clipboard.inner().get_text().as_deref(), &HashMap::new())?.text
+        }
+        ClipboardFormatContent::Text(text) => text.clone(),
+        _ => return Err(CmdError::InvalidArgument("only text records can be typed".to_string())),
+    };
+    if text.chars().count() > MAX_TYPE_CHARS {
+        return Err(CmdError::InvalidArgument(format!("text longer than {} characters should be pasted", MAX_TYPE_CHARS)));
+    }
+    log::debug!("type text: {}", logger::redact(&text));
+    hide_window(app)?;
+    match paste::type_text(&text, Duration::from_millis(delay_ms)) {
+        Ok(typed) => Ok(PasteResult::Typed(typed)),
+        // 已经输入了一部分，再放到剪贴板上用户会以为还没输入
+        Err(failure) if failure.chars > 0 => Err(CmdError::TypeInterrupted(failure.chars, failure.reason)),
+        Err(failure) => {
+            app.state::<ClipboardManager>().inner().paste(content).map_err(CmdError::ClipboardUnavailable)?;
+            if secret {
+                clear_secret_later(app);
+            }
+            Err(CmdError::PasteFailed(failure.reason))
+        }
+    }
+}
+
+fn paste_record(app: &AppHandle, id: i64, mode: PasteMode) -> CmdResult<PasteResult> {
+    let db = app.state::<StorageConn>();
+    let record = db.inner().get_record(id)?;
+    if record.content_type == SNIPPET_TYPE {
+        return paste_snippet_record(app, record, HashMap::new(), mode);
+    }
+    let secret = record.secret;
+    let content: ClipboardContent = record.try_into()?;
+    paste_content(app, content, None, mode, secret)
+}
+
+/// 在标签下创建一个片段
+#[tauri::command]
+pub fn create_snippet(app: AppHandle, tag_id: i64, text: String) -> CmdResult<ClipboardRecordVO> {
+    if tag_id == 0 {
+        return Err(Forbidden::SnippetInHistory.into());
+    }
+    let db = app.state::<StorageConn>();
+    let content = ClipboardContent {
+        main_format: ClipboardFormatContent::Text(text.clone()),
+        data: vec![ClipboardFormatContent::Text(text)],
+    };
+    let mut record: ClipboardRecord = content.try_into()?;
+    record.content_type = SNIPPET_TYPE.to_string();
+    let record = db.inner().insert_record_with_tag(record, tag_id).map_err(CmdError::from)?;
+    record.try_into()
+}
+
+/// 在标签下添加一条文本记录，供命令行通过 IPC 调用，加到剪贴板历史时通知前端
+pub fn add_text(app: AppHandle, tag_id: i64, text: String) -> CmdResult<ClipboardRecordVO> {
+    let db = app.state::<StorageConn>();
+    let content = ClipboardContent {
+        main_format: ClipboardFormatContent::Text(text.clone()),
+        data: vec![ClipboardFormatContent::Text(text)],
+    };
+    let record: ClipboardRecord = content.try_into()?;
+    let record: ClipboardRecordVO = db.inner().insert_record_with_tag(record, tag_id).map_err(CmdError::from)?.try_into()?;
+    if tag_id == 0 {
+        setup::emit_event(&app, Topic::CLIPBOARD_UPDATE, json!(record));
+    }
+    Ok(record)
+}
+
+/// 展开片段后粘贴，还有没输入的字段时通知前端弹出输入框
+fn paste_snippet_record(app: &AppHandle, record: ClipboardRecord, inputs: HashMap<String, String>, mode: PasteMode) -> CmdResult<PasteResult> {
+    let content = ClipboardContent::try_from(record.clone()).map_err(CmdError::from)?;
+    let text = match content.main_format {
+        ClipboardFormatContent::Text(text) => text,
+        _ => return Err(Forbidden::SnippetNotText.into()),
+    };
+    let template = Template::parse(&text);
+    let missing: Vec<String> = template.inputs().into_iter().filter(|name| !inputs.contains_key(name)).collect();
+    if !missing.is_empty() {
+        app.emit_all(Topic::SNIPPET_INPUT, json!({ "id": record.id, "inputs": missing, "mode": mode }))
+            .map_err(CmdError::from)?;
+        return Ok(PasteResult::WaitingForInput);
+    }
+
+    let clipboard = app.state::<ClipboardManager>();
+    let expanded = template.expand(clipboard.inner().get_text().as_deref(), &inputs)
+        .map_err(CmdError::from)?;
+    let content = ClipboardContent {
+        main_format: ClipboardFormatContent::Text(expanded.text.clone()),
+        data: vec![ClipboardFormatContent::Text(expanded.text)],
+    };
+    paste_content(app, content, expanded.cursor_offset, mode, record.secret)
+}
+
+/// 把内容放到剪贴板上，按 mode 发送粘贴事件，cursor_offset 为粘贴后光标需要左移的字符数
+///
+/// secret 为 true 时到时间后清空剪贴板，粘贴后恢复剪贴板的不需要
+pub(crate) fn paste_content(app: &AppHandle, content: ClipboardContent, cursor_offset: Option<usize>, mode: PasteMode, secret: bool) -> CmdResult<PasteResult> {
+    if let ClipboardFormatContent::Text(text) = &content.main_format {
+        log::debug!("{:?} text: {}", mode, logger::redact(text));
+    }
     let clipboard = app.state::<ClipboardManager>();
-    match db.inner().get_record(id) {
-        Ok(record) => {
-            let content: ClipboardContent = match record.try_into() {
-                Ok(_content) => _content,
-                Err(err) => return Err(err.to_string())
-            };
-            clipboard.inner().paste(content)?;
-            app.hide().unwrap();
-            let result = util::ClipboardUtil::paste_event();
-            println!("paste event result: {}", result);
-            Ok(result)
+    let pending_restore = match mode {
+        PasteMode::PasteAndRestore => clipboard.inner().paste_with_restore(content).map(Some),
+        _ => clipboard.inner().paste(content).map(|_| None),
+    }.map_err(CmdError::ClipboardUnavailable)?;
+    if secret && pending_restore.is_none() {
+        clear_secret_later(app);
+    }
+    if mode == PasteMode::CopyOnly {
+        return Ok(PasteResult::Copied);
+    }
+    hide_window(app)?;
+    let keystroke = paste::send_paste().map_err(CmdError::PasteFailed)?;
+    log::debug!("paste keystroke sent: {:?}", keystroke);
+    #[cfg(target_os = "macos")]
+    if let Some(offset) = cursor_offset.filter(|offset| *offset > 0) {
+        util::ClipboardUtil::move_cursor_left(offset);
+    }
+    #[cfg(not(target_os = "macos"))]
+    if cursor_offset.is_some() {
+        log::debug!("moving cursor after paste is only supported on macOS");
+    }
+    if let Some(pending_restore) = pending_restore {
+        pending_restore.restore();
+    }
+    Ok(PasteResult::Pasted(keystroke))
+}
+
+/// 按设置的时间安排清空剪贴板
+fn clear_secret_later(app: &AppHandle) {
+    if let Some(delay) = app.state::<PasteState>().secret_clear_delay() {
+        app.state::<ClipboardManager>().inner().clear_later(delay);
+    }
+}
+
+/// 隐藏窗口，让焦点回到之前的窗口
+pub fn hide_window(app: &AppHandle) -> CmdResult<()> {
+    // 使用 app.hide() 而不是 window.hide()，才能实现隐藏时焦点恢复到上一个应用
+    #[cfg(target_os = "macos")]
+    app.hide()?;
+    #[cfg(not(target_os = "macos"))]
+    if let Some(window) = app.get_window("main") {
+        window.hide()?;
+    }
+    Ok(())
+}
+
+/// 调整日志级别和是否在日志中记录剪贴板内容
+#[tauri::command]
+pub fn set_logging(app: AppHandle, level: Option<String>, log_content: Option<bool>) -> CmdResult<()> {
+    modify_settings(&app, |settings| {
+        if level.is_some() {
+            settings.log_level = level;
+        }
+        if log_content.is_some() {
+            settings.log_content = log_content;
         }
-        Err(err) => return Err(err.to_string())
+    })
+}
+
+/// 把日志、数据库版本和统计、配置打包到 path，用于反馈问题
+#[tauri::command]
+pub fn collect_diagnostics(app: AppHandle, path: String) -> CmdResult<String> {
+    let db = app.state::<StorageConn>();
+    let data_dir = app.path_resolver().app_data_dir()
+        .ok_or_else(|| CmdError::Internal("failed to fetch data directory".to_string()))?;
+    let info = json!({
+        "app_version": app.package_info().version.to_string(),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "generated_at": Local::now().to_rfc3339(),
+        "storage": db.inner().stats()?,
+        "backups": db.inner().list_backups()?,
+        "config": {
+            "log_level": logger::level().to_string(),
+            "log_content": logger::log_content(),
+        },
+    });
+    logger::write_diagnostics(Path::new(&path), &data_dir.join(logger::LOG_DIR), &info)
+        .map_err(|err| CmdError::StorageFailure(err.to_string()))?;
+    log::info!("diagnostics written to {}", path);
+    Ok(path)
+}
+
+/// 剪贴板监听器的健康状况
+#[tauri::command]
+pub fn listener_status(app: AppHandle) -> CmdResult<ListenerStatus> {
+    Ok(app.state::<ClipboardManager>().inner().status())
+}
+
+/// 当前的快捷键设置和注册失败的快捷键
+#[derive(Serialize, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub struct ShortcutsVO {
+    pub settings: ShortcutSettings,
+    pub failures: Vec<ShortcutFailure>,
+}
+
+#[tauri::command]
+pub fn get_shortcuts(app: AppHandle) -> CmdResult<ShortcutsVO> {
+    let state = app.state::<ShortcutState>();
+    Ok(ShortcutsVO { settings: state.settings(), failures: state.failures() })
+}
+
+/// 保存并重新注册快捷键，返回被其他应用占用等原因注册失败的快捷键
+#[tauri::command]
+pub fn update_shortcuts(app: AppHandle, settings: ShortcutSettings) -> CmdResult<Vec<ShortcutFailure>> {
+    modify_settings(&app, |current| current.shortcuts = settings)?;
+    Ok(app.state::<ShortcutState>().failures())
+}
+
+/// 暂停或恢复记录剪贴板
+#[tauri::command]
+pub fn set_capture_paused(app: AppHandle, paused: bool) -> CmdResult<ListenerStatus> {
+    let status = app.state::<ClipboardManager>().inner().set_paused(paused);
+    app.emit_all(Topic::LISTENER_STATUS, &status)?;
+    Ok(status)
+}
+
+/// 获取当前的设置
+#[tauri::command]
+pub fn get_settings(app: AppHandle) -> CmdResult<Settings> {
+    Ok(app.state::<SettingsState>().get())
+}
+
+/// 保存设置，变化的部分立即生效并通过 SETTINGS_UPDATE 通知前端
+#[tauri::command]
+pub fn update_settings(app: AppHandle, settings: Settings) -> CmdResult<()> {
+    app.state::<SettingsState>().update(settings).map_err(CmdError::from)
+}
+
+/// 修改一部分设置并保存
+fn modify_settings(app: &AppHandle, f: impl FnOnce(&mut Settings)) -> CmdResult<()> {
+    let state = app.state::<SettingsState>();
+    let mut settings = state.get();
+    f(&mut settings);
+    state.update(settings).map_err(CmdError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1×1 PNG 的文件头，足够读出尺寸
+    const PNG_HEADER: [u8; 24] = [137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1, 0, 0, 0, 1];
+
+    fn snapshot(content_type: &str, format: ClipboardFormatContent) -> String {
+        i18n::set_locale(Some(i18n::Locale::En));
+        let content = ClipboardContent { main_format: format.clone(), data: vec![format] };
+        let mut record: ClipboardRecord = content.try_into().unwrap();
+        record.id = 7;
+        record.time = 1700000000000;
+        record.content_type = content_type.to_string();
+        serde_json::to_string(&ClipboardRecordVO::try_from(record).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn text_record_vo() {
+        assert_eq!(
+            snapshot("text", ClipboardFormatContent::Text("héllo".to_string())),
+            r#"{"id":7,"time":1700000000000,"description":"5 characters","secret":false,"type":"text","data":"héllo"}"#,
+        );
+    }
+
+    #[test]
+    fn snippet_record_vo() {
+        assert_eq!(
+            snapshot(SNIPPET_TYPE, ClipboardFormatContent::Text("Hi {{input:Name}}{{cursor}}".to_string())),
+            r#"{"id":7,"time":1700000000000,"description":"27 characters","secret":false,"type":"snippet","data":"Hi {{input:Name}}{{cursor}}","inputs":["Name"]}"#,
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rtf_record_vo() {
+        assert_eq!(
+            snapshot("rtf", ClipboardFormatContent::RTF(b"{\\rtf1 a}".to_vec())),
+            r#"{"id":7,"time":1700000000000,"description":"9 characters","secret":false,"type":"rtf","data":{"rtf":"e1xydGYxIGF9","text":9}}"#,
+        );
+    }
+
+    #[test]
+    fn image_record_vo() {
+        assert_eq!(
+            snapshot("image", ClipboardFormatContent::Image(PNG_HEADER.to_vec())),
+            r#"{"id":7,"time":1700000000000,"description":"1 × 1","secret":false,"type":"image","data":"iVBORw0KGgoAAAANSUhEUgAAAAEAAAAB"}"#,
+        );
+    }
+}
@@ -0,0 +1,17 @@
+use serde::{Serialize, Deserialize};
+
+/// 不同格式的剪贴板数据
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ClipboardFormatContent {
+    Text(String),
+    RTF(Vec<u8>),
+    Image(Vec<u8>),
+    Files(Vec<String>),   // ([file_url...])
+}
+
+/// 剪贴板内容
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClipboardContent {
+    pub main_format: ClipboardFormatContent,
+    pub data: Vec<ClipboardFormatContent>
+}
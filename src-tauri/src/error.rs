@@ -0,0 +1,272 @@
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+use ts_rs::TS;
+
+use crate::{archive::ArchiveError, i18n, settings::SettingsError, storage::StorageError, sync::SyncError, template::TemplateError};
+
+/// 命令返回给前端的错误，前端根据 code 区分错误类型，message 直接展示给用户
+#[derive(Debug)]
+pub enum CmdError {
+    /// 记录、标签或备份不存在
+    NotFound(String),
+    /// 不允许的操作，如删除剪贴板历史
+    Forbidden(Forbidden),
+    /// 参数不合法
+    InvalidArgument(String),
+    /// 数据或文件格式不正确
+    InvalidFormat(String),
+    /// 读写数据库或文件失败
+    StorageFailure(String),
+    /// 无法访问系统剪贴板
+    ClipboardUnavailable(String),
+    /// 内容已经放到剪贴板上，但无法发送粘贴按键
+    PasteFailed(String),
+    /// 模拟输入到第几个字符时失败，内容没有放到剪贴板上
+    TypeInterrupted(usize, String),
+    /// 同步失败，如网络错误、密钥不一致
+    SyncFailure(String),
+    Internal(String),
+}
+
+/// 不允许的操作，大多和剪贴板历史有关
+#[derive(Debug, Clone, Copy)]
+pub enum Forbidden {
+    DeleteHistory,
+    MoveHistory,
+    ReorderHistory,
+    NestUnderHistory,
+    LinkHistory,
+    SnippetInHistory,
+    NotSnippet,
+    SnippetNotText,
+    LinkedReadOnly,
+}
+
+/// 错误码
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub enum ErrorCode {
+    NotFound,
+    Forbidden,
+    InvalidArgument,
+    InvalidFormat,
+    StorageFailure,
+    ClipboardUnavailable,
+    PasteFailed,
+    SyncFailure,
+    Internal,
+}
+
+/// CmdError 序列化后的结构
+#[derive(Serialize, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub struct ErrorVO {
+    pub code: ErrorCode,
+    pub message: String,
+    /// 原始的错误信息，用于排查问题
+    #[ts(optional)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+pub type CmdResult<T> = Result<T, CmdError>;
+
+impl CmdError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CmdError::NotFound(_) => ErrorCode::NotFound,
+            CmdError::Forbidden(_) => ErrorCode::Forbidden,
+            CmdError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            CmdError::InvalidFormat(_) => ErrorCode::InvalidFormat,
+            CmdError::StorageFailure(_) => ErrorCode::StorageFailure,
+            CmdError::ClipboardUnavailable(_) => ErrorCode::ClipboardUnavailable,
+            CmdError::PasteFailed(_) | CmdError::TypeInterrupted(..) => ErrorCode::PasteFailed,
+            CmdError::SyncFailure(_) => ErrorCode::SyncFailure,
+            CmdError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// 展示给用户的信息，按当前语言翻译
+    pub fn message(&self) -> String {
+        match self {
+            CmdError::InvalidArgument(detail) => i18n::t_args(self.message_id(), [("detail", detail.as_str().into())]),
+            CmdError::TypeInterrupted(chars, _) => i18n::t_args(self.message_id(), [("chars", (*chars).into())]),
+            _ => i18n::t(self.message_id()),
+        }
+    }
+
+    /// 信息在 locales/ 中的 id
+    fn message_id(&self) -> &'static str {
+        match self {
+            CmdError::NotFound(_) => "error-not-found",
+            CmdError::Forbidden(forbidden) => match forbidden {
+                Forbidden::DeleteHistory => "error-delete-history",
+                Forbidden::MoveHistory => "error-move-history",
+                Forbidden::ReorderHistory => "error-reorder-history",
+                Forbidden::NestUnderHistory => "error-nest-under-history",
+                Forbidden::LinkHistory => "error-link-history",
+                Forbidden::SnippetInHistory => "error-snippet-in-history",
+                Forbidden::NotSnippet => "error-not-snippet",
+                Forbidden::SnippetNotText => "error-snippet-not-text",
+                Forbidden::LinkedReadOnly => "error-linked-read-only",
+            },
+            CmdError::InvalidArgument(_) => "error-invalid-argument",
+            CmdError::InvalidFormat(_) => "error-invalid-format",
+            CmdError::StorageFailure(_) => "error-storage-failure",
+            CmdError::ClipboardUnavailable(_) => "error-clipboard-unavailable",
+            CmdError::PasteFailed(_) => "error-paste-failed",
+            CmdError::TypeInterrupted(..) => "error-type-interrupted",
+            CmdError::SyncFailure(_) => "error-sync-failure",
+            CmdError::Internal(_) => "error-internal",
+        }
+    }
+
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            CmdError::Forbidden(_) => None,
+            CmdError::NotFound(detail)
+            | CmdError::InvalidArgument(detail)
+            | CmdError::InvalidFormat(detail)
+            | CmdError::StorageFailure(detail)
+            | CmdError::ClipboardUnavailable(detail)
+            | CmdError::PasteFailed(detail)
+            | CmdError::TypeInterrupted(_, detail)
+            | CmdError::SyncFailure(detail)
+            | CmdError::Internal(detail) => Some(detail.clone()),
+        }
+    }
+}
+
+impl fmt::Display for CmdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.detail() {
+            Some(detail) => write!(f, "{:?}: {} ({})", self.code(), self.message(), detail),
+            None => write!(f, "{:?}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for CmdError {}
+
+impl Serialize for CmdError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorVO {
+            code: self.code(),
+            message: self.message(),
+            detail: self.detail(),
+        }.serialize(serializer)
+    }
+}
+
+impl From<Forbidden> for CmdError {
+    fn from(value: Forbidden) -> Self {
+        CmdError::Forbidden(value)
+    }
+}
+
+impl From<StorageError> for CmdError {
+    fn from(value: StorageError) -> Self {
+        let detail = value.to_string();
+        match value {
+            StorageError::Sqlite(rusqlite::Error::QueryReturnedNoRows) => CmdError::NotFound(detail),
+            StorageError::Sqlite(_) | StorageError::Io(_) => CmdError::StorageFailure(detail),
+            StorageError::Serde(_) => CmdError::InvalidFormat(detail),
+            StorageError::Invalid(_) => CmdError::InvalidArgument(detail),
+            StorageError::LinkedReadOnly => CmdError::Forbidden(Forbidden::LinkedReadOnly),
+        }
+    }
+}
+
+impl From<ArchiveError> for CmdError {
+    fn from(value: ArchiveError) -> Self {
+        let detail = value.to_string();
+        match value {
+            ArchiveError::Storage(err) => err.into(),
+            ArchiveError::Io(_) => CmdError::StorageFailure(detail),
+            ArchiveError::Zip(_) | ArchiveError::Serde(_) | ArchiveError::Invalid(_) => CmdError::InvalidFormat(detail),
+        }
+    }
+}
+
+impl From<SyncError> for CmdError {
+    fn from(value: SyncError) -> Self {
+        match value {
+            SyncError::Storage(err) => err.into(),
+            SyncError::EmptySecret => CmdError::InvalidArgument(value.to_string()),
+            err => CmdError::SyncFailure(err.to_string()),
+        }
+    }
+}
+
+impl From<SettingsError> for CmdError {
+    fn from(value: SettingsError) -> Self {
+        let detail = value.to_string();
+        match value {
+            SettingsError::Io(_) => CmdError::StorageFailure(detail),
+            SettingsError::Serde(_) => CmdError::InvalidFormat(detail),
+            SettingsError::Invalid(_) => CmdError::InvalidArgument(detail),
+        }
+    }
+}
+
+impl From<TemplateError> for CmdError {
+    fn from(value: TemplateError) -> Self {
+        let detail = value.to_string();
+        match value {
+            TemplateError::InvalidDateFormat(_) => CmdError::InvalidFormat(detail),
+            TemplateError::MissingInput(_) => CmdError::InvalidArgument(detail),
+        }
+    }
+}
+
+impl From<serde_json::Error> for CmdError {
+    fn from(value: serde_json::Error) -> Self {
+        CmdError::InvalidFormat(value.to_string())
+    }
+}
+
+impl From<tauri::Error> for CmdError {
+    fn from(value: tauri::Error) -> Self {
+        CmdError::Internal(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::i18n::Locale;
+
+    use super::*;
+
+    #[test]
+    fn every_message_is_translated() {
+        let detail = String::new;
+        let forbidden = [
+            Forbidden::DeleteHistory,
+            Forbidden::MoveHistory,
+            Forbidden::ReorderHistory,
+            Forbidden::NestUnderHistory,
+            Forbidden::LinkHistory,
+            Forbidden::SnippetInHistory,
+            Forbidden::NotSnippet,
+            Forbidden::SnippetNotText,
+            Forbidden::LinkedReadOnly,
+        ];
+        let errors = forbidden.into_iter().map(CmdError::Forbidden).chain([
+            CmdError::NotFound(detail()),
+            CmdError::InvalidArgument(detail()),
+            CmdError::InvalidFormat(detail()),
+            CmdError::StorageFailure(detail()),
+            CmdError::ClipboardUnavailable(detail()),
+            CmdError::PasteFailed(detail()),
+            CmdError::TypeInterrupted(3, detail()),
+            CmdError::SyncFailure(detail()),
+            CmdError::Internal(detail()),
+        ]);
+        for err in errors {
+            for locale in Locale::ALL {
+                assert!(i18n::has_message(locale, err.message_id()), "{:?} has no {:?} message", err, locale);
+            }
+        }
+    }
+}
@@ -1,5 +1,29 @@
 #![allow(non_snake_case)]
+use serde_json::Value;
+
 pub mod Topic {
     pub const CLIPBOARD_UPDATE: &'static str = "CLIPBOARD_UPDATE";
     pub const CLIPBOARD_DELETE: &'static str = "CLIPBOARD_DELETE";
-}
\ No newline at end of file
+    pub const SYNC_COMPLETE: &'static str = "SYNC_COMPLETE";
+    pub const LINKED_TAG_UPDATE: &'static str = "LINKED_TAG_UPDATE";
+    pub const SNIPPET_INPUT: &'static str = "SNIPPET_INPUT";
+    pub const LISTENER_STATUS: &'static str = "LISTENER_STATUS";
+    pub const SHORTCUT_FAILURE: &'static str = "SHORTCUT_FAILURE";
+    pub const OPEN_SEARCH: &'static str = "OPEN_SEARCH";
+    pub const SETTINGS_UPDATE: &'static str = "SETTINGS_UPDATE";
+    /// 数据库被整体替换（如从备份恢复），需要重新加载标签和记录
+    pub const STORAGE_RELOAD: &'static str = "STORAGE_RELOAD";
+}
+
+/// 事件的接收方，存储层等模块通过它发出通知而不依赖 Tauri
+///
+/// 界面中转发给前端，命令行和测试可以直接传入闭包
+pub trait EventSink: Send + Sync + 'static {
+    fn emit(&self, topic: &'static str, payload: Value);
+}
+
+impl<F> EventSink for F where F: Fn(&'static str, Value) + Send + Sync + 'static {
+    fn emit(&self, topic: &'static str, payload: Value) {
+        self(topic, payload)
+    }
+}
@@ -0,0 +1,148 @@
+//! 界面文字的多语言支持，翻译保存在 locales/ 下的 Fluent 文件中
+use std::{collections::HashMap, sync::{Mutex, OnceLock}};
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use unic_langid::LanguageIdentifier;
+
+/// 支持的语言
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    En,
+    Zh,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Zh];
+
+    fn source(&self) -> &'static str {
+        match self {
+            Locale::En => include_str!("../locales/en.ftl"),
+            Locale::Zh => include_str!("../locales/zh.ftl"),
+        }
+    }
+
+    fn langid(&self) -> LanguageIdentifier {
+        match self {
+            Locale::En => "en-US",
+            Locale::Zh => "zh-CN",
+        }.parse().unwrap()
+    }
+
+    /// 系统语言为中文时使用中文，其他情况使用英文
+    pub fn detect() -> Locale {
+        match sys_locale::get_locale() {
+            Some(locale) if locale.to_lowercase().starts_with("zh") => Locale::Zh,
+            _ => Locale::En,
+        }
+    }
+}
+
+/// 当前使用的语言，为空时还没有设置，第一次使用时按系统语言检测
+static LOCALE: Mutex<Option<Locale>> = Mutex::new(None);
+static BUNDLES: OnceLock<HashMap<Locale, FluentBundle<FluentResource>>> = OnceLock::new();
+
+/// 设置使用的语言，为空时使用系统语言
+pub fn set_locale(locale: Option<Locale>) {
+    *LOCALE.lock().unwrap() = Some(locale.unwrap_or_else(Locale::detect));
+}
+
+pub fn locale() -> Locale {
+    *LOCALE.lock().unwrap().get_or_insert_with(Locale::detect)
+}
+
+fn bundles() -> &'static HashMap<Locale, FluentBundle<FluentResource>> {
+    BUNDLES.get_or_init(|| Locale::ALL.iter().map(|locale| {
+        let resource = FluentResource::try_new(locale.source().to_string())
+            .unwrap_or_else(|(resource, errors)| {
+                log::error!("failed to parse {:?} messages: {:?}", locale, errors);
+                resource
+            });
+        let mut bundle = FluentBundle::new_concurrent(vec![locale.langid()]);
+        // 不在参数两侧插入 Unicode 方向隔离符，否则日志和原生菜单中会显示出来
+        bundle.set_use_isolating(false);
+        if let Err(errors) = bundle.add_resource(resource) {
+            log::error!("failed to load {:?} messages: {:?}", locale, errors);
+        }
+        (*locale, bundle)
+    }).collect())
+}
+
+/// 按当前语言翻译，找不到时返回 id
+pub fn t(id: &str) -> String {
+    format(locale(), id, None)
+}
+
+/// 带参数翻译，如 t_args("char-count", [("count", 3.into())])
+pub fn t_args<'a>(id: &str, args: impl IntoIterator<Item = (&'a str, FluentValue<'a>)>) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(name, value);
+    }
+    format(locale(), id, Some(&fluent_args))
+}
+
+/// 按指定的语言翻译
+pub fn t_in(locale: Locale, id: &str) -> String {
+    format(locale, id, None)
+}
+
+/// 指定的语言中是否有这条消息
+pub fn has_message(locale: Locale, id: &str) -> bool {
+    bundles()[&locale].has_message(id)
+}
+
+fn format(locale: Locale, id: &str, args: Option<&FluentArgs>) -> String {
+    let bundle = &bundles()[&locale];
+    let pattern = match bundle.get_message(id).and_then(|message| message.value()) {
+        Some(pattern) => pattern,
+        None => {
+            log::warn!("missing {:?} message {}", locale, id);
+            return id.to_string();
+        }
+    };
+    let mut errors = vec![];
+    let text = bundle.format_pattern(pattern, args, &mut errors);
+    if !errors.is_empty() {
+        log::warn!("failed to format {:?} message {}: {:?}", locale, id, errors);
+    }
+    text.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use fluent_syntax::{ast::Entry, parser};
+
+    use super::*;
+
+    fn message_ids(locale: Locale) -> BTreeSet<&'static str> {
+        let resource = parser::parse(locale.source()).unwrap_or_else(|(_, errors)| panic!("{:?}: {:?}", locale, errors));
+        resource.body.into_iter().filter_map(|entry| match entry {
+            Entry::Message(message) => Some(message.id.name),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn locales_have_the_same_messages() {
+        let en = message_ids(Locale::En);
+        assert!(!en.is_empty());
+        for locale in Locale::ALL {
+            assert_eq!(message_ids(locale), en, "{:?}", locale);
+        }
+    }
+
+    #[test]
+    fn arguments_are_formatted() {
+        let mut args = FluentArgs::new();
+        args.set("count", 1);
+        assert_eq!(format(Locale::En, "char-count", Some(&args)), "1 character");
+        assert!(has_message(Locale::Zh, "char-count"));
+        assert!(!has_message(Locale::En, "no-such-message"));
+    }
+}
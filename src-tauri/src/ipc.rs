@@ -0,0 +1,318 @@
+use std::{fs, io::{self, BufRead, BufReader, Write}, os::unix::{fs::PermissionsExt, net::{UnixListener, UnixStream}}, path::{Path, PathBuf}, sync::{Arc, Mutex, mpsc::{self, SyncSender, TrySendError}}, thread};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tauri::AppHandle;
+
+use crate::{cmd, event::Topic, paste::PasteMode};
+
+/// 数据目录下的 socket 文件名
+pub const SOCKET_NAME: &str = "drawer.sock";
+
+/// JSON-RPC 错误码
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const APP_ERROR: i64 = -32000;
+
+/// 订阅后推送给客户端的事件
+const SUBSCRIBE_TOPICS: [&str; 2] = [Topic::CLIPBOARD_UPDATE, Topic::CLIPBOARD_DELETE];
+/// 每个连接最多排队等待发送的行数，订阅者的队列满了说明它没有在读，取消订阅
+const OUTBOX_CAPACITY: usize = 64;
+
+/// 订阅了事件的连接，id 用于连接断开时移除
+type Subscribers = Mutex<Vec<(u64, SyncSender<String>)>>;
+
+/// 一行一个 JSON-RPC 2.0 请求
+#[derive(Deserialize)]
+struct Request {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListParams {
+    #[serde(default)]
+    tag_id: i64,
+    include_descendants: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchParams {
+    query: String,
+    tag_id: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TypeParams {
+    id: i64,
+    delay_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PasteParams {
+    id: i64,
+    mode: Option<PasteMode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddParams {
+    text: String,
+    #[serde(default)]
+    tag_id: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PinParams {
+    record_id: i64,
+    tag_id: i64,
+}
+
+/// 本地 IPC 服务，通过 Unix socket 提供和前端相同的操作，socket 只有当前用户可以读写
+pub struct IpcServer {
+    path: PathBuf,
+    /// 订阅了事件的连接，断开或者不读时移除
+    subscribers: Arc<Subscribers>,
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl IpcServer {
+    pub fn start(app: AppHandle, path: &Path) -> io::Result<IpcServer> {
+        Self::bind(path, move |method, params| Self::dispatch(&app, method, params))
+    }
+
+    /// 监听 socket，请求交给 handler 处理
+    fn bind<F>(path: &Path, handler: F) -> io::Result<IpcServer>
+    where F: Fn(&str, Value) -> Result<Value, Value> + Send + Sync + 'static {
+        if path.exists() {
+            // 能连上说明已经有实例在运行，否则是上次退出时残留的文件
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{} is in use", path.display())));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+        let subscribers: Arc<Subscribers> = Arc::new(Mutex::new(Vec::new()));
+        let server = IpcServer { path: path.to_path_buf(), subscribers: subscribers.clone() };
+        let handler = Arc::new(handler);
+        thread::spawn(move || for (id, stream) in (0..).zip(listener.incoming()) {
+            match stream {
+                Ok(stream) => {
+                    let handler = handler.clone();
+                    let subscribers = subscribers.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = Self::serve(&*handler, id, stream, &subscribers) {
+                            log::warn!("ipc connection closed with error: {}", err);
+                        }
+                        subscribers.lock().unwrap().retain(|(subscriber, _)| *subscriber != id);
+                    });
+                }
+                Err(err) => log::warn!("failed to accept ipc connection: {}", err),
+            }
+        });
+        Ok(server)
+    }
+
+    /// 把事件推送给订阅了的客户端，不在订阅范围内的事件忽略
+    ///
+    /// 只放进各个连接的发送队列，不会阻塞，队列满了的订阅者被移除
+    pub fn publish(&self, topic: &str, payload: &Value) {
+        if !SUBSCRIBE_TOPICS.contains(&topic) {
+            return;
+        }
+        let line = json!({ "jsonrpc": "2.0", "method": topic, "params": payload }).to_string();
+        self.subscribers.lock().unwrap().retain(|(id, outbox)| match outbox.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                log::warn!("ipc subscriber {} is not reading, unsubscribed", id);
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+
+    /// 处理一个连接的请求。响应和事件都交给同一个写线程按行发送，不会交错
+    fn serve<F>(handler: &F, id: u64, stream: UnixStream, subscribers: &Subscribers) -> io::Result<()>
+    where F: Fn(&str, Value) -> Result<Value, Value> {
+        let reader = BufReader::new(stream.try_clone()?);
+        let (outbox, lines) = mpsc::sync_channel::<String>(OUTBOX_CAPACITY);
+        let mut writer = stream;
+        thread::spawn(move || {
+            for line in lines {
+                if let Err(err) = writeln!(writer, "{}", line) {
+                    log::warn!("failed to write to ipc connection: {}", err);
+                    break;
+                }
+            }
+        });
+        let send = |line: String| outbox.send(line)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "ipc writer has exited"));
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => request,
+                Err(err) => {
+                    send(json!({ "jsonrpc": "2.0", "id": Value::Null, "error": { "code": PARSE_ERROR, "message": err.to_string() } }).to_string())?;
+                    continue;
+                }
+            };
+            let subscribe = request.method == "subscribe";
+            let result = if subscribe { Ok(json!(SUBSCRIBE_TOPICS)) } else { handler(&request.method, request.params) };
+            // 没有 id 的是通知，不需要响应
+            if let Some(request_id) = request.id {
+                send(match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": request_id, "result": result }),
+                    Err(error) => json!({ "jsonrpc": "2.0", "id": request_id, "error": error }),
+                }.to_string())?;
+            }
+            // 响应之后再订阅，事件不会先于订阅的响应到达
+            if subscribe {
+                let mut subscribers = subscribers.lock().unwrap();
+                if !subscribers.iter().any(|(subscriber, _)| *subscriber == id) {
+                    subscribers.push((id, outbox.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 出错时返回 JSON-RPC 的 error 对象，命令本身的错误放在 data 中
+    fn dispatch(app: &AppHandle, method: &str, params: Value) -> Result<Value, Value> {
+        let app = app.clone();
+        let result = match method {
+            "list" => {
+                let p: ListParams = Self::params(params)?;
+                cmd::get_all_record(app, p.tag_id, p.include_descendants).map(|records| json!(records))
+            }
+            "search" => {
+                let p: SearchParams = Self::params(params)?;
+                cmd::search_records(app, p.query, p.tag_id, p.limit).map(|records| json!(records))
+            }
+            "paste" => {
+                let p: PasteParams = Self::params(params)?;
+                cmd::paste(app, p.id, p.mode).map(|result| json!(result))
+            }
+            "type" => {
+                let p: TypeParams = Self::params(params)?;
+                cmd::type_record_blocking(&app, p.id, p.delay_ms).map(|result| json!(result))
+            }
+            "copy" => {
+                let p: IdParams = Self::params(params)?;
+                cmd::copy_record(app, p.id).map(|result| json!(result))
+            }
+            "add" => {
+                let p: AddParams = Self::params(params)?;
+                cmd::add_text(app, p.tag_id, p.text).map(|record| json!(record))
+            }
+            "pin" => {
+                let p: PinParams = Self::params(params)?;
+                cmd::pin_record(app, p.record_id, p.tag_id).map(|_| Value::Null)
+            }
+            "delete" => {
+                let p: IdParams = Self::params(params)?;
+                cmd::delete_record(app, p.id).map(|_| Value::Null)
+            }
+            "tags" => cmd::get_all_tags(app).map(|tags| json!(tags)),
+            _ => return Err(json!({ "code": METHOD_NOT_FOUND, "message": format!("method {} not found", method) })),
+        };
+        result.map_err(|err| json!({ "code": APP_ERROR, "message": err.message(), "data": err }))
+    }
+
+    fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, Value> {
+        // 没有参数时按空对象处理
+        let params = if params.is_null() { json!({}) } else { params };
+        serde_json::from_value(params).map_err(|err| json!({ "code": INVALID_PARAMS, "message": err.to_string() }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn subscriber_receives_clipboard_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SOCKET_NAME);
+        let server = IpcServer::bind(&path, |method, _| match method {
+            "ping" => Ok(json!("pong")),
+            _ => Err(json!({ "code": METHOD_NOT_FOUND })),
+        }).unwrap();
+
+        let stream = UnixStream::connect(&path).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut read = || {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            serde_json::from_str::<Value>(&line).unwrap()
+        };
+
+        writeln!(writer, r#"{{"jsonrpc":"2.0","id":1,"method":"ping"}}"#).unwrap();
+        assert_eq!(read()["result"], "pong");
+        writeln!(writer, r#"{{"jsonrpc":"2.0","id":2,"method":"subscribe"}}"#).unwrap();
+        assert_eq!(read()["result"], json!(SUBSCRIBE_TOPICS));
+
+        // 不在订阅范围内的事件不推送
+        server.publish(Topic::SETTINGS_UPDATE, &json!({}));
+        server.publish(Topic::CLIPBOARD_UPDATE, &json!({ "id": 1 }));
+        let event = read();
+        assert_eq!(event["method"], Topic::CLIPBOARD_UPDATE);
+        assert_eq!(event["params"]["id"], 1);
+    }
+
+    #[test]
+    fn stalled_subscriber_does_not_block_publish() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SOCKET_NAME);
+        let server = IpcServer::bind(&path, |_, _| Ok(Value::Null)).unwrap();
+        let subscribe = |path: &Path| {
+            let stream = UnixStream::connect(path).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            writeln!(&stream, r#"{{"jsonrpc":"2.0","id":1,"method":"subscribe"}}"#).unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            (stream, reader)
+        };
+
+        // 订阅后不再读取，socket 缓冲区写满后发送队列也会满
+        let _stalled = subscribe(&path);
+        let payload = json!({ "text": "x".repeat(16 * 1024) });
+        for _ in 0..1000 {
+            server.publish(Topic::CLIPBOARD_UPDATE, &payload);
+        }
+        assert!(server.subscribers.lock().unwrap().is_empty());
+
+        let (_stream, mut reader) = subscribe(&path);
+        server.publish(Topic::CLIPBOARD_DELETE, &json!([1]));
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap()["method"], Topic::CLIPBOARD_DELETE);
+    }
+}
@@ -0,0 +1,7 @@
+//! 不依赖 Tauri 的存储层和它用到的模块，界面和命令行共用，也可以单独测试
+pub mod content;
+pub mod event;
+pub mod i18n;
+pub mod linked;
+pub mod storage;
+pub mod template;
@@ -0,0 +1,232 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}};
+
+use chrono::{DateTime, Local};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+
+use crate::{content::{ClipboardContent, ClipboardFormatContent}, storage::ClipboardRecord, template::{Template, SNIPPET_TYPE}};
+
+/// 单个片段文件的大小上限，超过的文件忽略
+const MAX_SNIPPET_SIZE: u64 = 1024 * 1024;
+
+type LinkedListener = Box<dyn Fn(i64) + Send + Sync + 'static>;
+
+/// 关联目录中的一个片段文件
+///
+/// 文件开头可以有 front-matter 指定名称和标签，标签用于让片段同时出现在同名的本地标签下：
+/// ```text
+/// ---
+/// name: 部署命令
+/// tags: ops, shell
+/// ---
+/// kubectl rollout restart deployment/web
+/// ```
+#[derive(Clone, Debug)]
+pub struct LinkedSnippet {
+    pub id: i64,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub text: String,
+    pub path: PathBuf,
+    pub time: i64,
+}
+
+impl LinkedSnippet {
+    fn load(path: &Path) -> Option<LinkedSnippet> {
+        let metadata = fs::metadata(path).ok()?;
+        if !metadata.is_file() || metadata.len() > MAX_SNIPPET_SIZE {
+            return None;
+        }
+        let content = fs::read_to_string(path).ok()?;
+        let (front_matter, text) = split_front_matter(&content);
+        let mut name = None;
+        let mut tags = Vec::new();
+        for line in front_matter.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some(kv) => kv,
+                None => continue,
+            };
+            let value = value.trim();
+            match key.trim() {
+                "name" | "title" => name = Some(value.trim_matches('"').to_string()),
+                "tags" => tags = value.trim_start_matches('[').trim_end_matches(']')
+                    .split(',')
+                    .map(|tag| tag.trim().trim_matches('"').to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect(),
+                _ => {}
+            }
+        }
+        Some(LinkedSnippet {
+            id: snippet_id(path),
+            name: name.or_else(|| path.file_stem().map(|stem| stem.to_string_lossy().to_string())),
+            tags,
+            text: text.to_string(),
+            path: path.to_path_buf(),
+            time: metadata.modified().map(|t| DateTime::<Local>::from(t).timestamp_millis()).unwrap_or(0),
+        })
+    }
+
+    /// 转成剪贴板记录，和本地记录一起返回给前端
+    pub fn to_record(&self) -> ClipboardRecord {
+        let text = ClipboardFormatContent::Text(self.text.clone());
+        let content = ClipboardContent { main_format: text.clone(), data: vec![text.clone()] };
+        ClipboardRecord {
+            id: self.id,
+            main_data: serde_json::to_string(&text).unwrap_or_default(),
+            data: serde_json::to_string(&content).unwrap_or_default(),
+            // 带占位符的文件作为片段模板
+            content_type: if Template::has_placeholders(&self.text) { SNIPPET_TYPE } else { "text" }.to_string(),
+            time: self.time,
+            name: self.name.clone(),
+            secret: false,
+        }
+    }
+}
+
+/// 拆出 `---` 包围的 front-matter，没有时整个文件都是正文
+fn split_front_matter(content: &str) -> (&str, &str) {
+    let rest = match content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) {
+        Some(rest) => rest,
+        None => return ("", content),
+    };
+    for end in ["\n---\n", "\n---\r\n", "\r\n---\r\n"] {
+        if let Some(i) = rest.find(end) {
+            return (&rest[..i], &rest[i + end.len()..]);
+        }
+    }
+    match rest.strip_suffix("\n---") {
+        Some(front_matter) => (front_matter, ""),
+        None => ("", content),
+    }
+}
+
+/// 关联记录的 id 由文件路径生成，取负数以免和本地记录冲突
+fn snippet_id(path: &Path) -> i64 {
+    let digest = Sha256::digest(path.to_string_lossy().as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes[2..].copy_from_slice(&digest[..6]);
+    -(i64::from_be_bytes(bytes)) - 1
+}
+
+fn scan_dir(dir: &Path, snippets: &mut Vec<LinkedSnippet>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::warn!("failed to read linked directory {}: {}", dir.display(), err);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // 跳过 .git 等隐藏文件
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            scan_dir(&path, snippets);
+        } else if let Some(snippet) = LinkedSnippet::load(&path) {
+            snippets.push(snippet);
+        }
+    }
+}
+
+/// 关联目录中的片段库，每个关联标签对应一个目录，目录变化时自动重新加载
+#[derive(Default)]
+pub struct LinkedLibrary {
+    snippets: Arc<Mutex<HashMap<i64, Vec<LinkedSnippet>>>>,
+    watchers: Mutex<HashMap<i64, RecommendedWatcher>>,
+    listener: Arc<Mutex<Option<LinkedListener>>>,
+    /// 为 false 时只加载目录不监听
+    watching: AtomicBool,
+}
+
+impl std::fmt::Debug for LinkedLibrary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkedLibrary").field("snippets", &self.snippets).finish()
+    }
+}
+
+impl LinkedLibrary {
+    /// 目录内容变化时回调，参数为标签 id
+    pub fn set_listener<F>(&self, listener: F) where F: Fn(i64) + Send + Sync + 'static {
+        *self.listener.lock().unwrap() = Some(Box::new(listener));
+    }
+
+    /// 之后关联的目录是否监听变化
+    pub fn set_watching(&self, watching: bool) {
+        self.watching.store(watching, Ordering::SeqCst);
+    }
+
+    /// 把标签关联到目录，加载目录，开启监听时同时监听目录
+    pub fn link(&self, tag_id: i64, dir: &Path) {
+        self.unlink(tag_id);
+        Self::reload(&self.snippets, tag_id, dir);
+        if !self.watching.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let snippets = self.snippets.clone();
+        let listener = self.listener.clone();
+        let watch_dir = dir.to_path_buf();
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            match res {
+                Ok(event) if event.kind.is_access() => {}
+                Ok(_) => {
+                    Self::reload(&snippets, tag_id, &watch_dir);
+                    if let Some(listener) = &*listener.lock().unwrap() {
+                        listener(tag_id);
+                    }
+                }
+                Err(err) => log::warn!("failed to watch linked directory: {}", err),
+            }
+        }).and_then(|mut watcher| watcher.watch(dir, RecursiveMode::Recursive).map(|_| watcher));
+        match watcher {
+            Ok(watcher) => {
+                self.watchers.lock().unwrap().insert(tag_id, watcher);
+            }
+            Err(err) => log::warn!("failed to watch linked directory {}: {}", dir.display(), err),
+        }
+    }
+
+    /// 取消标签的关联
+    pub fn unlink(&self, tag_id: i64) {
+        self.watchers.lock().unwrap().remove(&tag_id);
+        self.snippets.lock().unwrap().remove(&tag_id);
+    }
+
+    fn reload(snippets: &Mutex<HashMap<i64, Vec<LinkedSnippet>>>, tag_id: i64, dir: &Path) {
+        let mut loaded = Vec::new();
+        scan_dir(dir, &mut loaded);
+        loaded.sort_by(|a, b| a.path.cmp(&b.path));
+        snippets.lock().unwrap().insert(tag_id, loaded);
+    }
+
+    /// 某个标签下的关联记录：关联到该标签的目录中的片段，以及 front-matter 标签与之同名的片段
+    pub fn records(&self, tag_id: i64, tag_name: &str) -> Vec<ClipboardRecord> {
+        let snippets = self.snippets.lock().unwrap();
+        let mut ret: Vec<ClipboardRecord> = Vec::new();
+        for (linked_tag_id, list) in snippets.iter() {
+            for snippet in list {
+                let matches = *linked_tag_id == tag_id || snippet.tags.iter().any(|tag| tag == tag_name);
+                if matches && !ret.iter().any(|r| r.id == snippet.id) {
+                    ret.push(snippet.to_record());
+                }
+            }
+        }
+        ret
+    }
+
+    /// 按 id 获取一条关联记录
+    pub fn get(&self, id: i64) -> Option<ClipboardRecord> {
+        self.snippets.lock().unwrap()
+            .values()
+            .flatten()
+            .find(|snippet| snippet.id == id)
+            .map(LinkedSnippet::to_record)
+    }
+
+    pub fn is_linked_id(id: i64) -> bool {
+        id < 0
+    }
+}
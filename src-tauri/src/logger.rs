@@ -0,0 +1,160 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, Write}, path::{Path, PathBuf}, str::FromStr, sync::{Mutex, atomic::{AtomicBool, Ordering}}};
+
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+use zip::{ZipWriter, write::FileOptions, CompressionMethod, result::ZipResult};
+
+/// 数据目录下存放日志的目录
+pub const LOG_DIR: &str = "logs";
+/// 日志文件名，轮转后为 drawer.1.log、drawer.2.log ...
+const LOG_NAME: &str = "drawer";
+/// 单个日志文件的大小上限
+const MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+/// 保留的日志文件数
+const LOG_GENERATIONS: usize = 5;
+/// 通过环境变量设置日志级别，如 DRAWER_LOG=debug
+const LEVEL_ENV: &str = "DRAWER_LOG";
+/// 通过环境变量允许日志中记录剪贴板内容，如 DRAWER_LOG_CONTENT=1
+const CONTENT_ENV: &str = "DRAWER_LOG_CONTENT";
+
+/// 默认不在日志中记录剪贴板内容
+static LOG_CONTENT: AtomicBool = AtomicBool::new(false);
+
+/// 写到数据目录下的日志，每行一条 JSON，超过大小上限后轮转
+struct FileLogger {
+    dir: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl FileLogger {
+    fn path(dir: &Path, generation: usize) -> PathBuf {
+        match generation {
+            0 => dir.join(format!("{}.log", LOG_NAME)),
+            n => dir.join(format!("{}.{}.log", LOG_NAME, n)),
+        }
+    }
+
+    fn open(dir: &Path) -> Option<File> {
+        OpenOptions::new().create(true).append(true).open(Self::path(dir, 0)).ok()
+    }
+
+    /// 当前日志文件过大时依次重命名，最旧的一份被覆盖
+    fn rotate(&self, file: &mut Option<File>) {
+        let size = file.as_ref().and_then(|f| f.metadata().ok()).map_or(0, |m| m.len());
+        if size < MAX_LOG_SIZE {
+            return;
+        }
+        for generation in (0..LOG_GENERATIONS - 1).rev() {
+            let from = Self::path(&self.dir, generation);
+            if from.exists() {
+                let _ = fs::rename(&from, Self::path(&self.dir, generation + 1));
+            }
+        }
+        *file = Self::open(&self.dir);
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = json!({
+            "time": Local::now().to_rfc3339(),
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        });
+        #[cfg(debug_assertions)]
+        eprintln!("{} {:<5} [{}] {}", Local::now().format("%H:%M:%S%.3f"), record.level(), record.target(), record.args());
+
+        let mut file = self.file.lock().unwrap();
+        self.rotate(&mut file);
+        if let Some(file) = file.as_mut() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// 初始化日志，级别和是否记录内容可以通过环境变量覆盖
+pub fn init(log_dir: &Path) {
+    if let Err(err) = fs::create_dir_all(log_dir) {
+        eprintln!("failed to create log directory {}: {}", log_dir.display(), err);
+    }
+    let logger = FileLogger {
+        dir: log_dir.to_path_buf(),
+        file: Mutex::new(FileLogger::open(log_dir)),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_err() {
+        return;
+    }
+    let level = std::env::var(LEVEL_ENV).ok()
+        .and_then(|level| LevelFilter::from_str(&level).ok())
+        .unwrap_or(LevelFilter::Info);
+    log::set_max_level(level);
+    LOG_CONTENT.store(std::env::var(CONTENT_ENV).is_ok_and(|v| v == "1"), Ordering::SeqCst);
+}
+
+/// 运行时调整日志级别
+pub fn set_level(level: &str) -> Result<(), String> {
+    let level = LevelFilter::from_str(level).map_err(|_| format!("unknown log level {}", level))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+pub fn level() -> LevelFilter {
+    log::max_level()
+}
+
+/// 是否在日志中记录剪贴板内容
+pub fn set_log_content(enabled: bool) {
+    LOG_CONTENT.store(enabled, Ordering::SeqCst);
+}
+
+pub fn log_content() -> bool {
+    LOG_CONTENT.load(Ordering::SeqCst)
+}
+
+/// 剪贴板内容写入日志前先经过这里，默认只记录长度
+pub fn redact(content: &str) -> String {
+    if log_content() {
+        content.to_string()
+    } else {
+        format!("<redacted {} chars>", content.chars().count())
+    }
+}
+
+/// 所有日志文件，从新到旧
+pub fn log_files(log_dir: &Path) -> Vec<PathBuf> {
+    (0..LOG_GENERATIONS)
+        .map(|generation| FileLogger::path(log_dir, generation))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// 打包诊断信息：所有日志文件和 info 中的版本、数据库统计、配置等
+pub fn write_diagnostics(path: &Path, log_dir: &Path, info: &Value) -> ZipResult<()> {
+    log::logger().flush();
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(File::create(path)?);
+    zip.start_file("info.json", options)?;
+    zip.write_all(serde_json::to_string_pretty(info).map_err(io::Error::from)?.as_bytes())?;
+    for log_file in log_files(log_dir) {
+        let name = log_file.file_name().unwrap_or_default().to_string_lossy().to_string();
+        zip.start_file(format!("{}/{}", LOG_DIR, name), options)?;
+        io::copy(&mut File::open(&log_file)?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
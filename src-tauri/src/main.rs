@@ -5,24 +5,76 @@
 
 mod setup;
 mod cmd;
+mod error;
+mod logger;
+mod paste;
+mod placement;
+mod settings;
+mod shortcut;
+mod cli;
+mod archive;
+mod sync;
+#[cfg(unix)]
+mod ipc;
 mod clipboard;
-mod event;
-mod storage;
 mod util;
 
+use drawer::{event, i18n, storage, template};
+
 fn main() {
+    let context = tauri::generate_context!();
+    // 带子命令启动时以命令行模式运行，不启动界面
+    if let Some(code) = cli::run(context.config(), context.package_info()) {
+        std::process::exit(code);
+    }
+    if let Some(data_dir) = tauri::api::path::app_data_dir(context.config()) {
+        logger::init(&data_dir.join(logger::LOG_DIR));
+    }
+
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             cmd::create_tag, 
             cmd::get_all_record,
+            cmd::search_records,
             cmd::get_all_tags,
             cmd::delete_record,
             cmd::create_tag,
             cmd::delete_tag,
+            cmd::update_tag,
+            cmd::set_tag_linked_dir,
+            cmd::reorder_tags,
+            cmd::move_tag,
+            cmd::get_descendant_tags,
             cmd::pin_record,
+            cmd::move_record,
             cmd::paste,
+            cmd::copy_record,
+            cmd::type_record,
+            cmd::get_paste_mode,
+            cmd::set_paste_mode,
+            cmd::set_record_secret,
+            cmd::set_secret_clear_delay,
+            cmd::paste_snippet,
+            cmd::create_snippet,
+            cmd::export_archive,
+            cmd::import_archive,
+            cmd::list_backups,
+            cmd::create_backup,
+            cmd::restore_backup,
+            cmd::start_sync_server,
+            cmd::stop_sync_server,
+            cmd::sync_with_peer,
+            cmd::discover_sync_peers,
+            cmd::set_logging,
+            cmd::collect_diagnostics,
+            cmd::listener_status,
+            cmd::set_capture_paused,
+            cmd::get_shortcuts,
+            cmd::update_shortcuts,
+            cmd::get_settings,
+            cmd::update_settings,
         ])
         .setup(setup::init)
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
@@ -0,0 +1,16 @@
+use crate::util::ClipboardUtil;
+
+use super::{Keystroke, PasteBackend, PasteKeys};
+
+/// 通过 AppleScript 发送 Command+V，失败时脚本返回 "error: ..."
+pub fn send_paste() -> Result<Keystroke, String> {
+    let result = ClipboardUtil::paste_event();
+    if result.starts_with("error:") {
+        return Err(result);
+    }
+    Ok(Keystroke {
+        backend: PasteBackend::AppleScript,
+        keys: PasteKeys::CmdV,
+        window_class: None,
+    })
+}
@@ -0,0 +1,188 @@
+//! 向之前获得焦点的窗口发送粘贴按键，各平台的实现在子模块中
+use std::{sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+#[cfg(target_os = "macos")]
+mod mac;
+#[cfg(target_os = "linux")]
+mod x11;
+#[cfg(target_os = "linux")]
+mod uinput;
+
+/// 选中记录后的操作
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(rename_all = "camelCase")]
+pub enum PasteMode {
+    /// 只放到剪贴板上，不发送粘贴按键
+    CopyOnly,
+    /// 放到剪贴板上并粘贴
+    #[default]
+    Paste,
+    /// 粘贴后把剪贴板恢复为之前的内容
+    PasteAndRestore,
+}
+
+/// 粘贴敏感内容后默认多久清空剪贴板
+const SECRET_CLEAR_DELAY: Duration = Duration::from_secs(30);
+
+/// 粘贴相关的设置
+pub struct PasteState {
+    /// 调用时没有指定粘贴方式时使用的默认方式
+    mode: Mutex<PasteMode>,
+    /// 粘贴敏感内容后多久清空剪贴板，为 0 时不清空
+    secret_clear_delay: Mutex<Duration>,
+}
+
+impl Default for PasteState {
+    fn default() -> Self {
+        PasteState {
+            mode: Mutex::new(PasteMode::default()),
+            secret_clear_delay: Mutex::new(SECRET_CLEAR_DELAY),
+        }
+    }
+}
+
+impl PasteState {
+    pub fn mode(&self) -> PasteMode {
+        *self.mode.lock().unwrap()
+    }
+
+    pub fn set_mode(&self, mode: PasteMode) {
+        *self.mode.lock().unwrap() = mode;
+    }
+
+    pub fn secret_clear_delay(&self) -> Option<Duration> {
+        Some(*self.secret_clear_delay.lock().unwrap()).filter(|delay| !delay.is_zero())
+    }
+
+    pub fn set_secret_clear_delay(&self, delay: Duration) {
+        *self.secret_clear_delay.lock().unwrap() = delay;
+    }
+}
+
+/// 发送按键的方式，前端的类型中包含所有平台的，各平台只会构造其中一部分
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub enum PasteBackend {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    AppleScript,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    CgEvent,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    XTest,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    Uinput,
+}
+
+/// 粘贴使用的组合键
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub enum PasteKeys {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    CmdV,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    CtrlV,
+    /// 终端中 Ctrl+V 有其他用途，粘贴要用 Ctrl+Shift+V
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    CtrlShiftV,
+}
+
+/// 已经发送的粘贴按键
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(rename_all = "camelCase")]
+pub struct Keystroke {
+    pub backend: PasteBackend,
+    pub keys: PasteKeys,
+    /// 接收按键的窗口类名，无法获取时为空
+    #[ts(optional)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_class: Option<String>,
+}
+
+/// 逐个字符模拟键盘输入的结果
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(rename_all = "camelCase")]
+pub struct TypedText {
+    pub backend: PasteBackend,
+    /// 输入的字符数
+    pub chars: usize,
+}
+
+/// 模拟输入失败，chars 为失败前已经输入的字符数
+#[derive(Debug)]
+pub struct TypeFailure {
+    pub chars: usize,
+    pub reason: String,
+}
+
+impl From<String> for TypeFailure {
+    fn from(reason: String) -> Self {
+        TypeFailure { chars: 0, reason }
+    }
+}
+
+/// 向当前获得焦点的窗口发送粘贴按键
+#[cfg(target_os = "macos")]
+pub fn send_paste() -> Result<Keystroke, String> {
+    mac::send_paste()
+}
+
+/// 向当前获得焦点的窗口发送粘贴按键
+///
+/// Wayland 会话中 XTest 只能发给 XWayland 的窗口，先用 uinput 模拟键盘，失败时再尝试 XTest；
+/// X11 会话中顺序相反
+#[cfg(target_os = "linux")]
+pub fn send_paste() -> Result<Keystroke, String> {
+    if is_wayland_session() {
+        uinput::send_paste().or_else(|err| {
+            log::warn!("failed to paste with uinput, falling back to XTest: {}", err);
+            x11::send_paste()
+        })
+    } else {
+        x11::send_paste().or_else(|err| {
+            log::warn!("failed to paste with XTest, falling back to uinput: {}", err);
+            uinput::send_paste()
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_wayland_session() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn send_paste() -> Result<Keystroke, String> {
+    Err("synthetic paste is not supported on this platform".to_string())
+}
+
+/// 把文本当作键盘输入逐个字符发送给当前获得焦点的窗口，用于禁止粘贴的输入框
+#[cfg(target_os = "macos")]
+pub fn type_text(text: &str, delay: Duration) -> Result<TypedText, TypeFailure> {
+    crate::util::ClipboardUtil::type_text(text, delay.as_millis() as u64);
+    Ok(TypedText {
+        backend: PasteBackend::CgEvent,
+        chars: text.chars().count(),
+    })
+}
+
+/// 把文本当作键盘输入逐个字符发送给当前获得焦点的窗口，只支持 X11
+#[cfg(target_os = "linux")]
+pub fn type_text(text: &str, delay: Duration) -> Result<TypedText, TypeFailure> {
+    let chars = x11::type_text(text, delay)?;
+    Ok(TypedText {
+        backend: PasteBackend::XTest,
+        chars,
+    })
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn type_text(_text: &str, _delay: Duration) -> Result<TypedText, TypeFailure> {
+    Err("typing text is not supported on this platform".to_string().into())
+}
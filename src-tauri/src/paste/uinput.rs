@@ -0,0 +1,152 @@
+use std::{fs::{File, OpenOptions}, io::{self, Write}, mem, os::unix::{fs::OpenOptionsExt, io::AsRawFd}, slice, thread, time::Duration};
+
+use super::{Keystroke, PasteBackend, PasteKeys};
+
+const UINPUT_PATH: &str = "/dev/uinput";
+/// 虚拟键盘创建后要等一会儿才会被系统识别
+const DEVICE_SETTLE: Duration = Duration::from_millis(200);
+/// 按键发送后等事件被读走再移除设备
+const DEVICE_DRAIN: Duration = Duration::from_millis(50);
+
+/// linux/uinput.h 中的 ioctl，按 linux/ioctl.h 中 _IO/_IOW 的方式编码
+const UINPUT_IOCTL_BASE: u8 = b'U';
+const UI_DEV_CREATE: u64 = io(1);
+const UI_DEV_DESTROY: u64 = io(2);
+const UI_DEV_SETUP: u64 = iow(3, mem::size_of::<UinputSetup>());
+const UI_SET_EVBIT: u64 = iow(100, mem::size_of::<libc::c_int>());
+const UI_SET_KEYBIT: u64 = iow(101, mem::size_of::<libc::c_int>());
+
+/// 方向占 2 位、参数大小占 14 位、类型和序号各占 8 位
+const fn ioc(dir: u64, nr: u8, size: usize) -> u64 {
+    (dir << 30) | ((size as u64) << 16) | ((UINPUT_IOCTL_BASE as u64) << 8) | nr as u64
+}
+
+/// _IO(UINPUT_IOCTL_BASE, nr)
+const fn io(nr: u8) -> u64 {
+    ioc(0, nr, 0)
+}
+
+/// _IOW(UINPUT_IOCTL_BASE, nr, size)，方向为用户态写入内核
+const fn iow(nr: u8, size: usize) -> u64 {
+    ioc(1, nr, size)
+}
+
+/// linux/input-event-codes.h 中的事件类型和按键
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const SYN_REPORT: u16 = 0;
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_V: u16 = 47;
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// struct uinput_setup
+#[repr(C)]
+struct UinputSetup {
+    id: libc::input_id,
+    name: [u8; 80],
+    ff_effects_max: u32,
+}
+
+/// 通过 uinput 创建虚拟键盘发送 Ctrl+V，用于没有 X11 的环境，需要有 /dev/uinput 的写权限
+///
+/// 这种方式拿不到获得焦点的窗口，所以总是使用 Ctrl+V
+pub fn send_paste() -> Result<Keystroke, String> {
+    let device = VirtualKeyboard::create()
+        .map_err(|err| format!("failed to create virtual keyboard via {}: {}", UINPUT_PATH, err))?;
+    thread::sleep(DEVICE_SETTLE);
+    device.send(PasteKeys::CtrlV).map_err(|err| format!("failed to send key events: {}", err))?;
+    thread::sleep(DEVICE_DRAIN);
+    Ok(Keystroke {
+        backend: PasteBackend::Uinput,
+        keys: PasteKeys::CtrlV,
+        window_class: None,
+    })
+}
+
+/// 只能发送粘贴相关按键的虚拟键盘，drop 时移除
+struct VirtualKeyboard(File);
+
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        let _ = self.ioctl(UI_DEV_DESTROY, 0);
+    }
+}
+
+impl VirtualKeyboard {
+    fn create() -> io::Result<VirtualKeyboard> {
+        let file = OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open(UINPUT_PATH)?;
+        let device = VirtualKeyboard(file);
+        device.ioctl(UI_SET_EVBIT, EV_KEY as libc::c_ulong)?;
+        for key in [KEY_LEFTCTRL, KEY_LEFTSHIFT, KEY_V] {
+            device.ioctl(UI_SET_KEYBIT, key as libc::c_ulong)?;
+        }
+
+        let mut setup = UinputSetup {
+            id: libc::input_id { bustype: BUS_VIRTUAL, vendor: 0, product: 0, version: 1 },
+            name: [0; 80],
+            ff_effects_max: 0,
+        };
+        let name = b"Drawer virtual keyboard";
+        setup.name[..name.len()].copy_from_slice(name);
+        device.ioctl(UI_DEV_SETUP, &setup as *const UinputSetup as libc::c_ulong)?;
+        device.ioctl(UI_DEV_CREATE, 0)?;
+        Ok(device)
+    }
+
+    fn ioctl(&self, request: u64, arg: libc::c_ulong) -> io::Result<()> {
+        match unsafe { libc::ioctl(self.0.as_raw_fd(), request as _, arg) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    fn send(&self, keys: PasteKeys) -> io::Result<()> {
+        let mut modifiers = vec![KEY_LEFTCTRL];
+        if keys == PasteKeys::CtrlShiftV {
+            modifiers.push(KEY_LEFTSHIFT);
+        }
+        for &modifier in &modifiers {
+            self.key(modifier, true)?;
+        }
+        self.key(KEY_V, true)?;
+        self.key(KEY_V, false)?;
+        for &modifier in modifiers.iter().rev() {
+            self.key(modifier, false)?;
+        }
+        Ok(())
+    }
+
+    /// 按下或松开一个键，并发送同步事件
+    fn key(&self, code: u16, press: bool) -> io::Result<()> {
+        self.event(EV_KEY, code, press as i32)?;
+        self.event(EV_SYN, SYN_REPORT, 0)
+    }
+
+    fn event(&self, type_: u16, code: u16, value: i32) -> io::Result<()> {
+        let event = libc::input_event {
+            time: libc::timeval { tv_sec: 0, tv_usec: 0 },
+            type_,
+            code,
+            value,
+        };
+        let bytes = unsafe {
+            slice::from_raw_parts(&event as *const libc::input_event as *const u8, mem::size_of::<libc::input_event>())
+        };
+        (&self.0).write_all(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ioctl_numbers_match_uinput_header() {
+        assert_eq!(UI_DEV_CREATE, 0x5501);
+        assert_eq!(UI_DEV_DESTROY, 0x5502);
+        assert_eq!(UI_DEV_SETUP, 0x405c5503);
+        assert_eq!(UI_SET_EVBIT, 0x40045564);
+        assert_eq!(UI_SET_KEYBIT, 0x40045565);
+    }
+}
@@ -0,0 +1,281 @@
+use std::{ffi::CStr, os::raw::{c_int, c_uint, c_void}, ptr, thread, time::{Duration, Instant}};
+
+use x11_dl::{keysym, xlib::{Display, KeySym, Window, XClassHint, Xlib, False}};
+// x11-dl 中 XTest 的函数表名为 Xf86vmode
+use x11_dl::xtest::Xf86vmode as Xtst;
+
+use super::{Keystroke, PasteBackend, PasteKeys, TypeFailure};
+
+/// 本应用窗口的类名，隐藏窗口后要等焦点回到之前的窗口
+const OWN_CLASS: &str = env!("CARGO_PKG_NAME");
+/// 等待焦点离开本应用窗口的最长时间
+const FOCUS_TIMEOUT: Duration = Duration::from_millis(300);
+const FOCUS_POLL: Duration = Duration::from_millis(10);
+
+/// 常见终端的窗口类名（小写）
+const TERMINAL_CLASSES: [&str; 22] = [
+    "xterm", "uxterm", "urxvt", "rxvt", "st", "st-256color", "alacritty", "kitty", "foot",
+    "gnome-terminal", "gnome-terminal-server", "konsole", "xfce4-terminal", "mate-terminal",
+    "lxterminal", "qterminal", "terminator", "tilix", "terminology", "guake", "tilda",
+    "org.wezfurlong.wezterm",
+];
+
+/// 根据窗口类名选择粘贴的组合键
+fn keys_for_class(window_class: Option<&str>) -> PasteKeys {
+    match window_class {
+        Some(class) if TERMINAL_CLASSES.contains(&class.to_lowercase().as_str()) => PasteKeys::CtrlShiftV,
+        _ => PasteKeys::CtrlV,
+    }
+}
+
+/// 通过 XTest 向获得焦点的窗口发送 Ctrl+V，终端中为 Ctrl+Shift+V
+pub fn send_paste() -> Result<Keystroke, String> {
+    let conn = Connection::open()?;
+    let window_class = conn.wait_for_focus();
+    let keys = keys_for_class(window_class.as_deref());
+    conn.send(keys)?;
+    Ok(Keystroke {
+        backend: PasteBackend::XTest,
+        keys,
+        window_class,
+    })
+}
+
+/// 通过 XTest 逐个字符输入文本，返回输入的字符数
+///
+/// 键盘布局中没有的字符临时映射到一个空闲的键码上，输入完成后恢复
+pub fn type_text(text: &str, delay: Duration) -> Result<usize, TypeFailure> {
+    let conn = Connection::open()?;
+    conn.wait_for_focus();
+    let keymap = Keymap::load(&conn)?;
+    let shift = conn.keycode(keysym::XK_Shift_L as KeySym)?;
+    let mut remapped = false;
+    let mut count = 0;
+    let result = text.chars().filter(|ch| *ch != '\r').try_for_each(|ch| {
+        let keysym = keysym_for_char(ch);
+        match keymap.find(keysym) {
+            Some((keycode, shifted)) => {
+                if shifted {
+                    conn.fake_key(shift, true)?;
+                }
+                conn.tap(keycode)?;
+                if shifted {
+                    conn.fake_key(shift, false)?;
+                }
+            }
+            None => {
+                let spare = keymap.spare.ok_or_else(|| format!("no spare keycode to type {:?}", ch))?;
+                conn.remap(spare, keysym, keymap.keysyms_per_keycode);
+                remapped = true;
+                conn.tap(spare)?;
+                // 按键事件处理完之前不能改回映射，否则会输入错误的字符
+                unsafe { (conn.xlib.XSync)(conn.display, False) };
+            }
+        }
+        unsafe { (conn.xlib.XFlush)(conn.display) };
+        count += 1;
+        thread::sleep(delay);
+        Ok(())
+    });
+    if let (true, Some(spare)) = (remapped, keymap.spare) {
+        thread::sleep(REMAP_SETTLE);
+        conn.remap(spare, 0, keymap.keysyms_per_keycode);
+    }
+    result.map(|_| count).map_err(|reason| TypeFailure { chars: count, reason })
+}
+
+/// 字符对应的 keysym，Latin-1 字符和 keysym 相同，其他 Unicode 字符为 0x01000000 + 码点
+fn keysym_for_char(ch: char) -> KeySym {
+    match ch {
+        '\n' => keysym::XK_Return as KeySym,
+        '\t' => keysym::XK_Tab as KeySym,
+        ' '..='~' | '\u{a0}'..='\u{ff}' => ch as KeySym,
+        _ => 0x0100_0000 + ch as KeySym,
+    }
+}
+
+/// 恢复临时映射前等待最后一个按键被处理
+const REMAP_SETTLE: Duration = Duration::from_millis(50);
+
+/// 键盘映射表，用于查找字符对应的键码和是否需要 Shift
+struct Keymap {
+    min_keycode: c_int,
+    keysyms_per_keycode: c_int,
+    keysyms: Vec<KeySym>,
+    /// 没有映射任何 keysym 的键码，用于输入布局中没有的字符
+    spare: Option<c_uint>,
+}
+
+impl Keymap {
+    fn load(conn: &Connection) -> Result<Keymap, String> {
+        let (mut min_keycode, mut max_keycode) = (0, 0);
+        let mut keysyms_per_keycode = 0;
+        let keysyms = unsafe {
+            (conn.xlib.XDisplayKeycodes)(conn.display, &mut min_keycode, &mut max_keycode);
+            let count = max_keycode - min_keycode + 1;
+            let ptr = (conn.xlib.XGetKeyboardMapping)(conn.display, min_keycode as _, count, &mut keysyms_per_keycode);
+            if ptr.is_null() {
+                return Err("failed to get keyboard mapping".to_string());
+            }
+            let keysyms = std::slice::from_raw_parts(ptr, (count * keysyms_per_keycode) as usize).to_vec();
+            (conn.xlib.XFree)(ptr as *mut c_void);
+            keysyms
+        };
+        let per = keysyms_per_keycode.max(1) as usize;
+        let spare = keysyms.chunks(per)
+            .rposition(|syms| syms.iter().all(|sym| *sym == 0))
+            .map(|index| (min_keycode as usize + index) as c_uint);
+        Ok(Keymap { min_keycode, keysyms_per_keycode, keysyms, spare })
+    }
+
+    /// 查找 keysym 所在的键码，只看前两级（不按 Shift 和按 Shift）
+    fn find(&self, keysym: KeySym) -> Option<(c_uint, bool)> {
+        let per = self.keysyms_per_keycode.max(1) as usize;
+        self.keysyms.chunks(per).enumerate().find_map(|(index, syms)| {
+            let keycode = (self.min_keycode as usize + index) as c_uint;
+            syms.iter().take(2).position(|sym| *sym == keysym).map(|level| (keycode, level == 1))
+        })
+    }
+}
+
+/// 和 X server 的连接，库在运行时加载，没有 X11 时返回错误而不是无法启动
+struct Connection {
+    xlib: Xlib,
+    xtst: Xtst,
+    display: *mut Display,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { (self.xlib.XCloseDisplay)(self.display) };
+    }
+}
+
+impl Connection {
+    fn open() -> Result<Connection, String> {
+        let xlib = Xlib::open().map_err(|err| err.to_string())?;
+        let xtst = Xtst::open().map_err(|err| err.to_string())?;
+        let display = unsafe { (xlib.XOpenDisplay)(ptr::null()) };
+        if display.is_null() {
+            return Err("failed to open X display".to_string());
+        }
+        let conn = Connection { xlib, xtst, display };
+        let (mut event_base, mut error_base, mut major, mut minor) = (0, 0, 0, 0);
+        let supported = unsafe {
+            (conn.xtst.XTestQueryExtension)(conn.display, &mut event_base, &mut error_base, &mut major, &mut minor)
+        };
+        if supported == 0 {
+            return Err("XTest extension is not available".to_string());
+        }
+        Ok(conn)
+    }
+
+    /// 等焦点离开本应用的窗口，返回获得焦点的窗口类名
+    fn wait_for_focus(&self) -> Option<String> {
+        let start = Instant::now();
+        loop {
+            let class = self.focused_class();
+            let is_own = class.as_deref().is_some_and(|class| class.eq_ignore_ascii_case(OWN_CLASS));
+            if !is_own || start.elapsed() > FOCUS_TIMEOUT {
+                return class;
+            }
+            thread::sleep(FOCUS_POLL);
+        }
+    }
+
+    /// 获得焦点的窗口的类名，焦点在没有类名的子窗口上时向上查找
+    fn focused_class(&self) -> Option<String> {
+        let mut window: Window = 0;
+        let mut revert: c_int = 0;
+        unsafe { (self.xlib.XGetInputFocus)(self.display, &mut window, &mut revert) };
+        // 0 为 None，1 为 PointerRoot
+        while window > 1 {
+            if let Some(class) = self.class_of(window) {
+                return Some(class);
+            }
+            window = self.parent_of(window)?;
+        }
+        None
+    }
+
+    fn class_of(&self, window: Window) -> Option<String> {
+        unsafe {
+            let mut hint: XClassHint = std::mem::zeroed();
+            if (self.xlib.XGetClassHint)(self.display, window, &mut hint) == 0 {
+                return None;
+            }
+            let class = (!hint.res_class.is_null())
+                .then(|| CStr::from_ptr(hint.res_class).to_string_lossy().to_string());
+            for name in [hint.res_name, hint.res_class] {
+                if !name.is_null() {
+                    (self.xlib.XFree)(name as *mut c_void);
+                }
+            }
+            class
+        }
+    }
+
+    /// 父窗口，已经是顶层窗口时返回 None
+    fn parent_of(&self, window: Window) -> Option<Window> {
+        let (mut root, mut parent): (Window, Window) = (0, 0);
+        let mut children: *mut Window = ptr::null_mut();
+        let mut count: c_uint = 0;
+        unsafe {
+            if (self.xlib.XQueryTree)(self.display, window, &mut root, &mut parent, &mut children, &mut count) == 0 {
+                return None;
+            }
+            if !children.is_null() {
+                (self.xlib.XFree)(children as *mut c_void);
+            }
+        }
+        (parent != root).then_some(parent)
+    }
+
+    fn send(&self, keys: PasteKeys) -> Result<(), String> {
+        let mut modifiers = vec![self.keycode(keysym::XK_Control_L as KeySym)?];
+        if keys == PasteKeys::CtrlShiftV {
+            modifiers.push(self.keycode(keysym::XK_Shift_L as KeySym)?);
+        }
+        let v = self.keycode(keysym::XK_v as KeySym)?;
+        for &modifier in &modifiers {
+            self.fake_key(modifier, true)?;
+        }
+        self.tap(v)?;
+        for &modifier in modifiers.iter().rev() {
+            self.fake_key(modifier, false)?;
+        }
+        unsafe { (self.xlib.XFlush)(self.display) };
+        Ok(())
+    }
+
+    /// 按下并松开一个键
+    fn tap(&self, keycode: c_uint) -> Result<(), String> {
+        self.fake_key(keycode, true)?;
+        self.fake_key(keycode, false)
+    }
+
+    /// 把键码的前两级都映射为 keysym，keysym 为 0 时清除映射
+    fn remap(&self, keycode: c_uint, keysym: KeySym, keysyms_per_keycode: c_int) {
+        let mut keysyms = vec![0 as KeySym; keysyms_per_keycode.max(2) as usize];
+        keysyms[0] = keysym;
+        keysyms[1] = keysym;
+        unsafe {
+            (self.xlib.XChangeKeyboardMapping)(self.display, keycode as c_int, keysyms.len() as c_int, keysyms.as_mut_ptr(), 1);
+            (self.xlib.XSync)(self.display, False);
+        }
+    }
+
+    fn keycode(&self, keysym: KeySym) -> Result<c_uint, String> {
+        match unsafe { (self.xlib.XKeysymToKeycode)(self.display, keysym) } {
+            0 => Err(format!("no keycode for keysym {:#x}", keysym)),
+            keycode => Ok(keycode as c_uint),
+        }
+    }
+
+    fn fake_key(&self, keycode: c_uint, press: bool) -> Result<(), String> {
+        match unsafe { (self.xtst.XTestFakeKeyEvent)(self.display, keycode, press as c_int, 0) } {
+            0 => Err(format!("XTest rejected key event for keycode {}", keycode)),
+            _ => Ok(()),
+        }
+    }
+}
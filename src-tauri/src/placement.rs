@@ -0,0 +1,221 @@
+//! 每次显示抽屉时，按光标所在的屏幕和布局计算窗口的位置和大小
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Monitor, PhysicalPosition, PhysicalSize, Position, Size, Window};
+use ts_rs::TS;
+
+use crate::settings::SettingsState;
+
+/// 抽屉的布局
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(rename_all = "camelCase")]
+pub enum WindowLayout {
+    /// 屏幕底部，宽度和屏幕相同
+    #[default]
+    BottomStrip,
+    /// 屏幕顶部，宽度和屏幕相同
+    TopStrip,
+    /// 屏幕右侧，高度和屏幕相同
+    SidePanel,
+    /// 光标附近的弹出窗口
+    CenteredPopup,
+}
+
+/// 弹出窗口和光标之间的距离，逻辑像素
+const POPUP_OFFSET: f64 = 16.0;
+
+/// 屏幕上的矩形区域，使用物理像素
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Rect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Rect {
+    fn of(monitor: &Monitor) -> Rect {
+        let position = monitor.position();
+        let size = monitor.size();
+        Rect {
+            x: position.x as f64,
+            y: position.y as f64,
+            width: size.width as f64,
+            height: size.height as f64,
+        }
+    }
+
+    fn contains(&self, (x, y): (f64, f64)) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// 按布局计算窗口的区域
+///
+/// width 和 height 是设置中的逻辑大小，按屏幕的缩放比例换算成物理像素，超出屏幕时缩小到屏幕大小
+fn layout_rect(layout: WindowLayout, screen: Rect, scale: f64, cursor: Option<(f64, f64)>, width: f64, height: f64) -> Rect {
+    let width = (width * scale).min(screen.width);
+    let height = (height * scale).min(screen.height);
+    match layout {
+        WindowLayout::BottomStrip => Rect { y: screen.y + screen.height - height, height, ..screen },
+        WindowLayout::TopStrip => Rect { height, ..screen },
+        WindowLayout::SidePanel => Rect { x: screen.x + screen.width - width, width, ..screen },
+        WindowLayout::CenteredPopup => match cursor {
+            // 水平方向以光标为中心，放在光标下方，下方放不下时放在上方
+            Some((x, y)) => {
+                let offset = POPUP_OFFSET * scale;
+                let below = y + offset;
+                Rect {
+                    x: (x - width / 2.0).clamp(screen.x, screen.x + screen.width - width),
+                    y: if below + height <= screen.y + screen.height { below } else { (y - offset - height).max(screen.y) },
+                    width,
+                    height,
+                }
+            }
+            None => Rect {
+                x: screen.x + (screen.width - width) / 2.0,
+                y: screen.y + (screen.height - height) / 2.0,
+                width,
+                height,
+            },
+        },
+    }
+}
+
+/// 光标所在的屏幕，以及光标的物理坐标
+///
+/// 逻辑坐标按各个屏幕自己的缩放比例换算，和 Monitor::position 的算法一致
+fn monitor_at(monitors: Vec<Monitor>, cursor: Position) -> Option<(Monitor, (f64, f64))> {
+    monitors.into_iter().find_map(|monitor| {
+        let point = match cursor {
+            Position::Physical(p) => (p.x as f64, p.y as f64),
+            Position::Logical(p) => (p.x * monitor.scale_factor(), p.y * monitor.scale_factor()),
+        };
+        Rect::of(&monitor).contains(point).then_some((monitor, point))
+    })
+}
+
+/// 把窗口放到光标所在的屏幕上，获取不到光标位置时使用窗口当前所在的屏幕
+pub fn place(win: &Window, layout: WindowLayout, width: f64, height: f64) -> tauri::Result<()> {
+    let found = match cursor_position() {
+        Some(cursor) => monitor_at(win.available_monitors()?, cursor),
+        None => None,
+    };
+    let (monitor, cursor) = match found {
+        Some((monitor, cursor)) => (monitor, Some(cursor)),
+        None => match win.current_monitor()?.or(win.primary_monitor()?) {
+            Some(monitor) => (monitor, None),
+            None => {
+                log::warn!("no monitor found, window is not moved");
+                return Ok(());
+            }
+        },
+    };
+    let rect = layout_rect(layout, Rect::of(&monitor), monitor.scale_factor(), cursor, width, height);
+    // 先移动到目标屏幕再调整大小，以免移动到缩放比例不同的屏幕时窗口又被系统缩放
+    win.set_position(Position::Physical(PhysicalPosition::new(rect.x.round() as i32, rect.y.round() as i32)))?;
+    win.set_size(Size::Physical(PhysicalSize::new(rect.width.round() as u32, rect.height.round() as u32)))?;
+    Ok(())
+}
+
+/// 按当前设置放置主窗口
+pub fn place_main_window(app: &AppHandle) {
+    let settings = app.state::<SettingsState>().get();
+    let win = app.get_window("main").unwrap();
+    if let Err(err) = place(&win, settings.layout, settings.window_width, settings.window_height) {
+        log::error!("failed to place window: {}", err);
+    }
+}
+
+/// 光标的位置，NSEvent 的坐标原点在主屏幕左下角，转换为左上角
+#[cfg(target_os = "macos")]
+fn cursor_position() -> Option<Position> {
+    use cocoa::{base::id, foundation::{NSPoint, NSRect}};
+    use objc::{class, msg_send, sel, sel_impl};
+    use tauri::LogicalPosition;
+
+    unsafe {
+        let point: NSPoint = msg_send![class!(NSEvent), mouseLocation];
+        let screens: id = msg_send![class!(NSScreen), screens];
+        let count: usize = msg_send![screens, count];
+        if count == 0 {
+            return None;
+        }
+        let primary: id = msg_send![screens, objectAtIndex: 0usize];
+        let frame: NSRect = msg_send![primary, frame];
+        Some(Position::Logical(LogicalPosition::new(point.x, frame.size.height - point.y)))
+    }
+}
+
+/// 光标的位置，通过 X11 查询，没有 X11（如 Wayland）时返回 None
+#[cfg(target_os = "linux")]
+fn cursor_position() -> Option<Position> {
+    use std::{os::raw::{c_int, c_uint}, ptr};
+    use x11_dl::xlib::{Window, Xlib};
+
+    let xlib = Xlib::open().ok()?;
+    unsafe {
+        let display = (xlib.XOpenDisplay)(ptr::null());
+        if display.is_null() {
+            return None;
+        }
+        let root = (xlib.XDefaultRootWindow)(display);
+        let (mut root_ret, mut child): (Window, Window) = (0, 0);
+        let (mut x, mut y, mut win_x, mut win_y): (c_int, c_int, c_int, c_int) = (0, 0, 0, 0);
+        let mut mask: c_uint = 0;
+        let found = (xlib.XQueryPointer)(display, root, &mut root_ret, &mut child, &mut x, &mut y, &mut win_x, &mut win_y, &mut mask);
+        (xlib.XCloseDisplay)(display);
+        (found != 0).then(|| Position::Physical(PhysicalPosition::new(x, y)))
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn cursor_position() -> Option<Position> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2 倍缩放、位于主屏幕右侧的 2560×1440 屏幕
+    const SCREEN: Rect = Rect { x: 1920.0, y: 0.0, width: 2560.0, height: 1440.0 };
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    #[test]
+    fn strips_span_the_screen() {
+        assert_eq!(layout_rect(WindowLayout::BottomStrip, SCREEN, 2.0, None, 600.0, 350.0), rect(1920.0, 740.0, 2560.0, 700.0));
+        assert_eq!(layout_rect(WindowLayout::TopStrip, SCREEN, 2.0, None, 600.0, 350.0), rect(1920.0, 0.0, 2560.0, 700.0));
+        assert_eq!(layout_rect(WindowLayout::SidePanel, SCREEN, 2.0, None, 600.0, 350.0), rect(3280.0, 0.0, 1200.0, 1440.0));
+    }
+
+    #[test]
+    fn oversized_window_is_shrunk_to_the_screen() {
+        assert_eq!(layout_rect(WindowLayout::SidePanel, SCREEN, 2.0, None, 4000.0, 350.0), SCREEN);
+        assert_eq!(layout_rect(WindowLayout::CenteredPopup, SCREEN, 2.0, None, 600.0, 4000.0), rect(2600.0, 0.0, 1200.0, 1440.0));
+    }
+
+    #[test]
+    fn popup_is_centered_without_cursor() {
+        assert_eq!(layout_rect(WindowLayout::CenteredPopup, SCREEN, 2.0, None, 600.0, 350.0), rect(2600.0, 370.0, 1200.0, 700.0));
+    }
+
+    #[test]
+    fn popup_follows_cursor() {
+        // 光标下方，偏移量也按缩放比例换算
+        assert_eq!(layout_rect(WindowLayout::CenteredPopup, SCREEN, 2.0, Some((3000.0, 100.0)), 600.0, 350.0), rect(2400.0, 132.0, 1200.0, 700.0));
+        // 靠近屏幕边缘时不超出屏幕
+        assert_eq!(layout_rect(WindowLayout::CenteredPopup, SCREEN, 2.0, Some((1930.0, 100.0)), 600.0, 350.0), rect(1920.0, 132.0, 1200.0, 700.0));
+        assert_eq!(layout_rect(WindowLayout::CenteredPopup, SCREEN, 2.0, Some((4470.0, 100.0)), 600.0, 350.0), rect(3280.0, 132.0, 1200.0, 700.0));
+    }
+
+    #[test]
+    fn popup_flips_above_cursor() {
+        assert_eq!(layout_rect(WindowLayout::CenteredPopup, SCREEN, 2.0, Some((3000.0, 1000.0)), 600.0, 350.0), rect(2400.0, 268.0, 1200.0, 700.0));
+        // 上下都放不下时贴着屏幕顶部
+        assert_eq!(layout_rect(WindowLayout::CenteredPopup, SCREEN, 2.0, Some((3000.0, 720.0)), 600.0, 350.0), rect(2400.0, 0.0, 1200.0, 700.0));
+    }
+}
@@ -0,0 +1,388 @@
+//! 应用设置，以 JSON 保存在配置目录下，文件被外部修改时自动重新加载
+use std::{fmt, fs, io, path::{Path, PathBuf}, str::FromStr, sync::{Arc, Mutex}, time::Duration};
+
+use log::LevelFilter;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::{i18n::{self, Locale}, paste::PasteMode, placement::WindowLayout, shortcut::ShortcutSettings, storage::GcConfig};
+
+/// 配置目录下保存设置的文件
+pub const SETTINGS_FILE: &str = "settings.json";
+/// 旧版本单独保存快捷键的文件，启动时合并到设置中后删除
+const LEGACY_SHORTCUTS_FILE: &str = "shortcuts.json";
+
+/// 窗口宽度和高度的范围
+const WINDOW_SIZE_RANGE: (f64, f64) = (150.0, 4000.0);
+/// 剪贴板历史最多保留的条数
+const MAX_HISTORY_LIMIT: usize = 10000;
+/// 清理剪贴板历史的最长间隔，秒
+const MAX_GC_INTERVAL: u64 = 3600;
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    Serde(serde_json::Error),
+    /// 设置的值不合法
+    Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(err) => write!(f, "io error: {}", err),
+            SettingsError::Serde(err) => write!(f, "serde error: {}", err),
+            SettingsError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<io::Error> for SettingsError {
+    fn from(value: io::Error) -> Self {
+        SettingsError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for SettingsError {
+    fn from(value: serde_json::Error) -> Self {
+        SettingsError::Serde(value)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SettingsError>;
+
+/// 应用设置，文件中缺少的字段使用默认值
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// 界面语言，为空时跟随系统
+    pub locale: Option<Locale>,
+    /// 抽屉的布局
+    pub layout: WindowLayout,
+    /// 窗口高度，侧边栏布局时使用屏幕高度
+    pub window_height: f64,
+    /// 侧边栏和弹出窗口的宽度，顶部和底部布局时使用屏幕宽度
+    pub window_width: f64,
+    /// 剪贴板历史保留的条数
+    pub history_limit: usize,
+    /// 清理剪贴板历史的间隔，秒
+    #[ts(type = "number")]
+    pub gc_interval: u64,
+    /// 没有指定粘贴方式时使用的默认方式
+    pub paste_mode: PasteMode,
+    /// 粘贴敏感内容后多少秒清空剪贴板，为 0 时不清空
+    #[ts(type = "number")]
+    pub secret_clear_delay: u64,
+    /// 日志级别，为空时使用环境变量 DRAWER_LOG 或 info
+    pub log_level: Option<String>,
+    /// 是否在日志中记录剪贴板内容，为空时使用环境变量 DRAWER_LOG_CONTENT
+    pub log_content: Option<bool>,
+    pub shortcuts: ShortcutSettings,
+    pub tray: TraySettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            locale: None,
+            layout: WindowLayout::default(),
+            window_height: 350.0,
+            window_width: 600.0,
+            history_limit: 100,
+            gc_interval: 10,
+            paste_mode: PasteMode::default(),
+            secret_clear_delay: 30,
+            log_level: None,
+            log_content: None,
+            shortcuts: ShortcutSettings::default(),
+            tray: TraySettings::default(),
+        }
+    }
+}
+
+/// 托盘菜单的文字，为空时使用当前语言的默认文字
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(default)]
+pub struct TraySettings {
+    pub about: Option<String>,
+    pub quit: Option<String>,
+}
+
+impl TraySettings {
+    pub fn about(&self) -> String {
+        self.about.clone().unwrap_or_else(|| i18n::t("tray-about"))
+    }
+
+    pub fn quit(&self) -> String {
+        self.quit.clone().unwrap_or_else(|| i18n::t("tray-quit"))
+    }
+}
+
+impl Settings {
+    /// 读取设置，文件不存在或格式不正确时使用默认设置
+    pub fn load(path: &Path) -> Settings {
+        match Self::read(path) {
+            Ok(settings) => settings,
+            Err(SettingsError::Io(err)) if err.kind() == io::ErrorKind::NotFound => Settings::default(),
+            Err(err) => {
+                log::warn!("failed to load {}, using defaults: {}", path.display(), err);
+                Settings::default()
+            }
+        }
+    }
+
+    /// 读取并检查设置
+    fn read(path: &Path) -> Result<Settings> {
+        let settings: Settings = serde_json::from_str(&fs::read_to_string(path)?)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// 先写临时文件再改名，文件监听不会读到写了一半的内容
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let (min_size, max_size) = WINDOW_SIZE_RANGE;
+        for (name, size) in [("windowHeight", self.window_height), ("windowWidth", self.window_width)] {
+            if !(min_size..=max_size).contains(&size) {
+                return Err(SettingsError::Invalid(format!("{} must be between {} and {}", name, min_size, max_size)));
+            }
+        }
+        if !(1..=MAX_HISTORY_LIMIT).contains(&self.history_limit) {
+            return Err(SettingsError::Invalid(format!("historyLimit must be between 1 and {}", MAX_HISTORY_LIMIT)));
+        }
+        if !(1..=MAX_GC_INTERVAL).contains(&self.gc_interval) {
+            return Err(SettingsError::Invalid(format!("gcInterval must be between 1 and {}", MAX_GC_INTERVAL)));
+        }
+        if let Some(level) = &self.log_level {
+            LevelFilter::from_str(level).map_err(|_| SettingsError::Invalid(format!("unknown log level {}", level)))?;
+        }
+        if [&self.tray.about, &self.tray.quit].iter().any(|label| label.as_deref().is_some_and(|label| label.trim().is_empty())) {
+            return Err(SettingsError::Invalid("tray labels must not be empty".to_string()));
+        }
+        self.shortcuts.validate().map_err(|err| SettingsError::Invalid(format!("shortcuts: {}", err)))?;
+        Ok(())
+    }
+
+    pub fn gc_config(&self) -> GcConfig {
+        GcConfig {
+            history_limit: self.history_limit,
+            interval: Duration::from_secs(self.gc_interval),
+        }
+    }
+
+    pub fn secret_clear_delay(&self) -> Duration {
+        Duration::from_secs(self.secret_clear_delay)
+    }
+}
+
+/// 设置变化时的回调，参数为变化前和变化后的设置
+pub type SettingsListener = Box<dyn Fn(&Settings, &Settings) + Send + Sync>;
+
+/// 当前的设置，修改和重新加载后通知监听者
+pub struct SettingsState {
+    path: PathBuf,
+    settings: Arc<Mutex<Settings>>,
+    listener: Arc<Mutex<Option<SettingsListener>>>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl SettingsState {
+    pub fn load(config_dir: &Path) -> SettingsState {
+        let path = config_dir.join(SETTINGS_FILE);
+        let mut settings = Settings::load(&path);
+        Self::migrate_shortcuts(config_dir, &path, &mut settings);
+        SettingsState {
+            settings: Arc::new(Mutex::new(settings)),
+            path,
+            listener: Arc::new(Mutex::new(None)),
+            watcher: Mutex::new(None),
+        }
+    }
+
+    /// 把旧版本的 shortcuts.json 合并到设置中，保存后删除
+    ///
+    /// 设置文件已经存在时说明已经迁移过，直接删除旧文件
+    fn migrate_shortcuts(config_dir: &Path, path: &Path, settings: &mut Settings) {
+        let legacy = config_dir.join(LEGACY_SHORTCUTS_FILE);
+        let content = match fs::read_to_string(&legacy) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => {
+                log::warn!("failed to read {}: {}", legacy.display(), err);
+                return;
+            }
+        };
+        if !path.exists() {
+            let shortcuts = serde_json::from_str::<ShortcutSettings>(&content).map_err(|err| err.to_string())
+                .and_then(|shortcuts| shortcuts.validate().map(|_| shortcuts));
+            match shortcuts {
+                Ok(shortcuts) => {
+                    let mut migrated = settings.clone();
+                    migrated.shortcuts = shortcuts;
+                    // 保存失败时保留旧文件，下次启动再迁移
+                    if let Err(err) = migrated.save(path) {
+                        log::warn!("failed to migrate {}: {}", legacy.display(), err);
+                        return;
+                    }
+                    *settings = migrated;
+                    log::info!("migrated shortcuts from {}", legacy.display());
+                }
+                Err(err) => log::warn!("ignored invalid shortcuts in {}: {}", legacy.display(), err),
+            }
+        }
+        if let Err(err) = fs::remove_file(&legacy) {
+            log::warn!("failed to remove {}: {}", legacy.display(), err);
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_listener<F>(&self, listener: F) where F: Fn(&Settings, &Settings) + Send + Sync + 'static {
+        *self.listener.lock().unwrap() = Some(Box::new(listener));
+    }
+
+    /// 检查并保存新的设置，不合法时返回错误且不会保存
+    pub fn update(&self, settings: Settings) -> Result<()> {
+        settings.validate()?;
+        let old = {
+            // 保存时持有锁，文件监听重新加载时读到的就是已经生效的设置，不会重复通知
+            let mut current = self.settings.lock().unwrap();
+            settings.save(&self.path)?;
+            std::mem::replace(&mut *current, settings.clone())
+        };
+        Self::notify(&self.listener, &old, &settings);
+        Ok(())
+    }
+
+    /// 监听设置文件，被外部修改后重新加载，格式不正确时保留当前设置
+    pub fn watch(&self) {
+        let dir = match self.path.parent() {
+            Some(dir) => dir,
+            None => return,
+        };
+        if let Err(err) = fs::create_dir_all(dir) {
+            log::warn!("failed to create config directory {}: {}", dir.display(), err);
+            return;
+        }
+        let path = self.path.clone();
+        let settings = self.settings.clone();
+        let listener = self.listener.clone();
+        // 编辑器保存时可能会先写临时文件再改名，所以监听整个目录
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            match res {
+                Ok(event) if event.kind.is_access() => {}
+                Ok(event) if event.paths.iter().any(|p| p.file_name() == path.file_name()) => {
+                    Self::reload(&path, &settings, &listener);
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!("failed to watch settings: {}", err),
+            }
+        }).and_then(|mut watcher| watcher.watch(dir, RecursiveMode::NonRecursive).map(|_| watcher));
+        match watcher {
+            Ok(watcher) => *self.watcher.lock().unwrap() = Some(watcher),
+            Err(err) => log::warn!("failed to watch config directory {}: {}", dir.display(), err),
+        }
+    }
+
+    fn reload(path: &Path, settings: &Mutex<Settings>, listener: &Mutex<Option<SettingsListener>>) {
+        // 在锁内读取，避免用旧的文件内容覆盖刚刚通过 update 保存的设置
+        let mut current = settings.lock().unwrap();
+        let new = match Settings::read(path) {
+            Ok(new) => new,
+            // 删除文件时保留当前设置，下次修改时会重新写入
+            Err(SettingsError::Io(err)) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => {
+                log::warn!("ignored invalid settings in {}: {}", path.display(), err);
+                return;
+            }
+        };
+        let old = std::mem::replace(&mut *current, new.clone());
+        drop(current);
+        if old != new {
+            log::info!("reloaded settings from {}", path.display());
+        }
+        Self::notify(listener, &old, &new);
+    }
+
+    fn notify(listener: &Mutex<Option<SettingsListener>>, old: &Settings, new: &Settings) {
+        if old == new {
+            return;
+        }
+        if let Some(listener) = &*listener.lock().unwrap() {
+            listener(old, new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(settings: Settings) -> String {
+        match settings.validate() {
+            Err(SettingsError::Invalid(msg)) => msg,
+            other => panic!("expected invalid settings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(Settings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        assert!(invalid(Settings { window_height: 100.0, ..Settings::default() }).starts_with("windowHeight"));
+        assert!(invalid(Settings { window_width: 5000.0, ..Settings::default() }).starts_with("windowWidth"));
+        assert!(invalid(Settings { history_limit: 0, ..Settings::default() }).starts_with("historyLimit"));
+        assert!(invalid(Settings { gc_interval: MAX_GC_INTERVAL + 1, ..Settings::default() }).starts_with("gcInterval"));
+    }
+
+    #[test]
+    fn unknown_log_level_is_rejected() {
+        assert!(Settings { log_level: Some("Debug".to_string()), ..Settings::default() }.validate().is_ok());
+        assert_eq!(invalid(Settings { log_level: Some("verbose".to_string()), ..Settings::default() }), "unknown log level verbose");
+    }
+
+    #[test]
+    fn conflicting_shortcuts_are_rejected() {
+        let mut settings = Settings::default();
+        settings.shortcuts.toggle = Some("Ctrl+Shift+V".to_string());
+        settings.shortcuts.paste_plain = Some("shift+control+v".to_string());
+        let msg = invalid(settings);
+        assert!(msg.starts_with("shortcuts: ") && msg.ends_with("both use shift+control+v"), "{}", msg);
+    }
+
+    #[test]
+    fn legacy_shortcuts_are_migrated_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let shortcuts = ShortcutSettings { toggle: Some("Alt+Space".to_string()), ..ShortcutSettings::default() };
+        fs::write(dir.path().join(LEGACY_SHORTCUTS_FILE), serde_json::to_string(&shortcuts).unwrap()).unwrap();
+
+        let state = SettingsState::load(dir.path());
+        assert_eq!(state.get().shortcuts, shortcuts);
+        assert!(!dir.path().join(LEGACY_SHORTCUTS_FILE).exists());
+        assert_eq!(Settings::load(&dir.path().join(SETTINGS_FILE)).shortcuts, shortcuts);
+
+        // 设置文件已经存在时不再覆盖其中的快捷键
+        fs::write(dir.path().join(LEGACY_SHORTCUTS_FILE), r#"{"toggle":"Alt+X"}"#).unwrap();
+        assert_eq!(SettingsState::load(dir.path()).get().shortcuts, shortcuts);
+        assert!(!dir.path().join(LEGACY_SHORTCUTS_FILE).exists());
+    }
+}
@@ -1,48 +1,56 @@
 use std::fs;
 
 use tauri::api::dialog::MessageDialogBuilder;
-use tauri::{Manager, Position, LogicalPosition, LogicalSize, Size, App, GlobalShortcutManager, WindowEvent, SystemTray, SystemTrayMenu, CustomMenuItem, SystemTrayEvent};
+use tauri::{AppHandle, Manager, App, WindowEvent, SystemTray, SystemTrayMenu, CustomMenuItem, SystemTrayEvent};
+#[cfg(target_os = "macos")]
 use window_vibrancy::NSVisualEffectMaterial;
 
-use crate::cmd::ClipboardRecordVO;
+use crate::cmd::{self, ClipboardRecordVO};
 use crate::storage::{StorageConn, ClipboardRecord};
-use crate::util::{self, WindowUtil};
+#[cfg(target_os = "macos")]
+use crate::util::WindowUtil;
 use crate::{clipboard::{ClipboardManager, ClipboardContent}, storage};
-use crate::event::Topic;
+use crate::event::{EventSink, Topic};
+use crate::i18n::{self, Locale};
+use crate::logger;
+use crate::paste::PasteState;
+use crate::placement;
+use crate::settings::{Settings, SettingsState};
+use crate::shortcut::{self, ShortcutState};
+use crate::sync::SyncState;
+#[cfg(unix)]
+use crate::ipc::{self, IpcServer};
 
 pub type AppError = Box<(dyn std::error::Error + 'static)>;
 pub type SetupResult = Result<(), AppError>;
 
 /// 设置窗口
 fn set_window(app: &mut App) -> SetupResult {
-    let win = app.get_window("main").unwrap();
-    const HEIGHT: f64 = 350.0;
-    // 设置大小和位置
-    let monitor = win.current_monitor()
-        .expect("failed to get monitor info")
-        .expect("failed to get monitor info");
-    let screen_size = monitor.size().to_logical::<f64>(monitor.scale_factor());
-    win.set_size(Size::Logical(LogicalSize::new(screen_size.width as f64, HEIGHT)))?;
-    win.set_position(Position::Logical(LogicalPosition::new(0.0, screen_size.height as f64 - HEIGHT)))?;
-    // 设置窗口层级为顶级（高于菜单栏和Dock，Tauri默认设置会在Dock栏下面）
-    WindowUtil::set_window_top_level(&win);
-    // 设置毛玻璃背景
+    // 设置大小和位置，之后每次显示时会按光标所在的屏幕重新计算
+    placement::place_main_window(&app.handle());
     #[cfg(target_os = "macos")]
-    window_vibrancy::apply_vibrancy(win, NSVisualEffectMaterial::Popover, None, None)
-        .expect("Unsupported platform! 'apply_vibrancy' is only supported on macOS");
-    // 将窗口设置成类似 NSPanel 的模式 https://github.com/tauri-apps/tauri/issues/2258
-    app.set_activation_policy(tauri::ActivationPolicy::Accessory);
+    {
+        let win = app.get_window("main").unwrap();
+        // 设置窗口层级为顶级（高于菜单栏和Dock，Tauri默认设置会在Dock栏下面）
+        WindowUtil::set_window_top_level(&win);
+        // 设置毛玻璃背景
+        window_vibrancy::apply_vibrancy(win, NSVisualEffectMaterial::Popover, None, None)
+            .expect("Unsupported platform! 'apply_vibrancy' is only supported on macOS");
+        // 将窗口设置成类似 NSPanel 的模式 https://github.com/tauri-apps/tauri/issues/2258
+        app.set_activation_policy(tauri::ActivationPolicy::Accessory);
+    }
     Ok(())
 }
 
 /// 设置菜单托盘
 fn set_tray(app: &mut App) -> SetupResult {
     let app_handle = app.handle();
+    let labels = app.state::<SettingsState>().get().tray;
     SystemTray::new()
       .with_menu(
         SystemTrayMenu::new()
-          .add_item(CustomMenuItem::new("about", "关于"))
-          .add_item(CustomMenuItem::new("quit", "退出 Drawer"))
+          .add_item(CustomMenuItem::new("about", labels.about()))
+          .add_item(CustomMenuItem::new("quit", labels.quit()))
       )
       .on_event(move | event| match event {
         SystemTrayEvent::MenuItemClick {  id  , .. } => {
@@ -50,9 +58,10 @@ fn set_tray(app: &mut App) -> SetupResult {
                 "about" => {
                     app_handle.get_window("main").unwrap().hide().unwrap();
                     MessageDialogBuilder
-                        ::new("Drawer", format!("{}\n\n{}\n\nDrawer是一个macOS上的剪贴板应用",
+                        ::new("Drawer", format!("{}\n\n{}\n\n{}",
                             app_handle.package_info().version,
-                            "https://github.com/xxxuuu/Drawer"
+                            "https://github.com/xxxuuu/Drawer",
+                            i18n::t("about-description"),
                         ))
                         .show(|_ok| {});
                 },
@@ -65,20 +74,11 @@ fn set_tray(app: &mut App) -> SetupResult {
     Ok(())
 }
 
-/// 注册全局快捷键
+/// 注册全局快捷键，失败的快捷键记录下来，前端可以通过 get_shortcuts 获取
 fn register_shortcut(app: &mut App) -> SetupResult {
-    let mut short_cut = app.global_shortcut_manager();
-    let app_handler = app.handle();
-    short_cut.register("shift+command+v", move || {
-        let window = app_handler.get_window("main").unwrap();
-        if window.is_visible().unwrap() {
-            // 使用 app.hide() 而不是 window.hide()，才能实现隐藏时焦点恢复到上一个应用
-            app_handler.hide().unwrap();
-        } else {
-            app_handler.show().unwrap();
-            window.set_focus().unwrap();
-        }
-    })?;
+    let settings = app.state::<SettingsState>().get().shortcuts;
+    app.manage(ShortcutState::new(settings));
+    shortcut::register_all(&app.handle());
     Ok(())
 }
 
@@ -90,7 +90,9 @@ fn register_window_event(app: &mut App) -> SetupResult {
         match e {
             WindowEvent::Focused(is_focus) => {
                 if !is_focus {
-                    app_handler.hide().unwrap();
+                    if let Err(err) = cmd::hide_window(&app_handler) {
+                        log::error!("failed to hide main window: {}", err);
+                    }
                 }
             },
             _ => {}
@@ -99,6 +101,23 @@ fn register_window_event(app: &mut App) -> SetupResult {
     Ok(())
 }
 
+/// 把后台模块的通知转发给前端
+fn event_sink(app: &App) -> impl EventSink {
+    let app_handler = app.handle();
+    move |topic: &'static str, payload: serde_json::Value| emit_event(&app_handler, topic, payload)
+}
+
+/// 通知前端，同时推送给订阅了事件的 IPC 客户端
+pub fn emit_event(app: &AppHandle, topic: &'static str, payload: serde_json::Value) {
+    #[cfg(unix)]
+    if let Some(server) = app.try_state::<IpcServer>() {
+        server.publish(topic, &payload);
+    }
+    if let Err(err) = app.emit_all(topic, payload) {
+        log::error!("failed to emit {}: {}", topic, err);
+    }
+}
+
 // 注册剪贴板事件
 fn register_clipboard_event(app: &mut App) -> SetupResult {
     let app_handler = app.handle();
@@ -107,29 +126,35 @@ fn register_clipboard_event(app: &mut App) -> SetupResult {
         let _record: ClipboardRecord = match c.try_into() {
             Ok(record) => record,
             Err(err) => {
-                println!("failed to convert ClipboardContent to ClipboardRecord {:?}", err);
+                log::error!("failed to convert ClipboardContent to ClipboardRecord {:?}", err);
                 return;
             }
         };
         let record = app_handler.state::<StorageConn>().insert_record(_record);
         match record {
             Ok(payload) => {
+                log::debug!("captured {} record {}: {}", payload.content_type, payload.id, logger::redact(&payload.main_data));
                 let vo: ClipboardRecordVO = match payload.try_into() {
                     Ok(_vo) => _vo,
                     Err(err) => {
-                        println!("failed to convert record to vo {:?}", err);
+                        log::error!("failed to convert record to vo {:?}", err);
                         return;
                     }
                 };
-                app_handler.emit_all(Topic::CLIPBOARD_UPDATE, vo).unwrap();
+                emit_event(&app_handler, Topic::CLIPBOARD_UPDATE, serde_json::json!(vo));
             },
             Err(err) => {
-                println!("failed to insert record {:?}", err);
+                log::error!("failed to insert record {:?}", err);
                 return;
             }
         }
-    });
+    }, event_sink(app));
     app.manage(cm);
+    let settings = app.state::<SettingsState>().get();
+    let paste = PasteState::default();
+    paste.set_mode(settings.paste_mode);
+    paste.set_secret_clear_delay(settings.secret_clear_delay());
+    app.manage(paste);
     Ok(())
 }
 
@@ -137,17 +162,108 @@ fn register_clipboard_event(app: &mut App) -> SetupResult {
 fn init_storage(app: &mut App) -> SetupResult {
     let data_dir = app.path_resolver().app_data_dir().expect("failed to fetch data directory");
     fs::create_dir_all(&data_dir).expect("failed to create data directory");
-    let conn = storage::StorageConn::new(&data_dir, app.handle()).expect("failed to create database connection");
+    let gc_config = app.state::<SettingsState>().get().gc_config();
+    let conn = storage::StorageConn::new(&data_dir, gc_config, event_sink(app)).expect("failed to create database connection");
+    localize_history_tag(&conn);
     app.handle().manage(conn);
+    app.manage(SyncState::default());
     Ok(())
 }
 
+/// 剪贴板历史标签还是某个语言的默认名称时，改为当前语言的名称
+fn localize_history_tag(db: &StorageConn) {
+    let defaults: Vec<String> = Locale::ALL.iter().map(|locale| i18n::t_in(*locale, "history-tag")).collect();
+    if let Err(err) = db.rename_history_tag(&defaults, &i18n::t("history-tag")) {
+        log::error!("failed to rename history tag: {}", err);
+    }
+}
+
+// 启动本地 IPC 服务，供编辑器、启动器等外部工具使用
+#[cfg(unix)]
+fn init_ipc(app: &mut App) -> SetupResult {
+    let data_dir = app.path_resolver().app_data_dir().expect("failed to fetch data directory");
+    match IpcServer::start(app.handle(), &data_dir.join(ipc::SOCKET_NAME)) {
+        Ok(server) => {
+            app.manage(server);
+        }
+        Err(err) => log::error!("failed to start ipc server: {}", err),
+    }
+    Ok(())
+}
+
+// 加载设置，其他模块初始化时从中读取
+fn init_settings(app: &mut App) -> SetupResult {
+    let config_dir = app.path_resolver().app_config_dir().expect("failed to fetch config directory");
+    let state = SettingsState::load(&config_dir);
+    i18n::set_locale(state.get().locale);
+    apply_logging(&state.get());
+    app.manage(state);
+    Ok(())
+}
+
+/// 日志设置为空时保留环境变量或默认的设置
+fn apply_logging(settings: &Settings) {
+    if let Some(level) = &settings.log_level {
+        if let Err(err) = logger::set_level(level) {
+            log::error!("failed to set log level: {}", err);
+        }
+    }
+    if let Some(log_content) = settings.log_content {
+        logger::set_log_content(log_content);
+    }
+}
+
+/// 监听设置的变化，包括通过命令修改和直接编辑设置文件
+fn watch_settings(app: &mut App) -> SetupResult {
+    let app_handle = app.handle();
+    let state = app.state::<SettingsState>();
+    state.set_listener(move |old, new| apply_settings(&app_handle, old, new));
+    state.watch();
+    Ok(())
+}
+
+/// 把变化后的设置通知给各模块和前端
+fn apply_settings(app: &AppHandle, old: &Settings, new: &Settings) {
+    if (old.layout, old.window_width, old.window_height) != (new.layout, new.window_width, new.window_height) {
+        placement::place_main_window(app);
+    }
+    app.state::<StorageConn>().set_gc_config(new.gc_config());
+    let paste = app.state::<PasteState>();
+    paste.set_mode(new.paste_mode);
+    paste.set_secret_clear_delay(new.secret_clear_delay());
+    apply_logging(new);
+    if old.shortcuts != new.shortcuts {
+        app.state::<ShortcutState>().set_settings(new.shortcuts.clone());
+        shortcut::register_all(app);
+    }
+    let locale_changed = old.locale != new.locale;
+    if locale_changed {
+        i18n::set_locale(new.locale);
+        localize_history_tag(&app.state::<StorageConn>());
+    }
+    if old.tray != new.tray || locale_changed {
+        let tray = app.tray_handle();
+        for (id, label) in [("about", new.tray.about()), ("quit", new.tray.quit())] {
+            if let Err(err) = tray.get_item(id).set_title(label) {
+                log::error!("failed to update tray item {}: {}", id, err);
+            }
+        }
+    }
+    if let Err(err) = app.emit_all(Topic::SETTINGS_UPDATE, new) {
+        log::error!("failed to emit settings update: {}", err);
+    }
+}
+
 pub fn init(app: &mut App) -> SetupResult {
+    init_settings(app)?;
     set_window(app)?;
     set_tray(app)?;
     init_storage(app)?;
     register_shortcut(app)?;
     register_window_event(app)?;
     register_clipboard_event(app)?;
+    #[cfg(unix)]
+    init_ipc(app)?;
+    watch_settings(app)?;
     Ok(())
 }
\ No newline at end of file
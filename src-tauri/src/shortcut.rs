@@ -0,0 +1,296 @@
+use std::{collections::{BTreeSet, HashMap}, fmt, sync::Mutex, thread};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, GlobalShortcutManager, Manager};
+use ts_rs::TS;
+
+use crate::{clipboard::{ClipboardContent, ClipboardFormatContent, ClipboardManager}, cmd, event::Topic, paste::PasteMode, placement, storage::StorageConn};
+
+/// 最多可以设置粘贴最近几条记录的快捷键
+pub const MAX_PASTE_NTH: usize = 9;
+
+/// F1～F24，没有修饰键也允许注册
+const FUNCTION_KEYS: usize = 24;
+/// 除字母、数字和 F1～F24 外支持的按键，和 Tauri 的 accelerator 一致
+const NAMED_KEYS: [&str; 31] = [
+    "space", "tab", "enter", "return", "escape", "esc", "backspace", "delete", "insert", "home", "end",
+    "pageup", "pagedown", "up", "down", "left", "right", "plus", "minus", "equal", "comma", "period",
+    "slash", "backslash", "semicolon", "quote", "backquote", "bracketleft", "bracketright", "printscreen",
+    "numlock",
+];
+
+/// 全局快捷键设置，为空时不注册
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShortcutSettings {
+    /// 显示或隐藏抽屉
+    pub toggle: Option<String>,
+    /// 去掉剪贴板内容的格式后粘贴
+    pub paste_plain: Option<String>,
+    /// 直接粘贴最近的第 1～9 条记录
+    pub paste_nth: Vec<Option<String>>,
+    /// 暂停或恢复记录剪贴板
+    pub pause_capture: Option<String>,
+    /// 打开抽屉并进入搜索
+    pub open_search: Option<String>,
+}
+
+impl Default for ShortcutSettings {
+    fn default() -> Self {
+        ShortcutSettings {
+            toggle: Some("shift+command+v".to_string()),
+            paste_plain: None,
+            paste_nth: vec![],
+            pause_capture: None,
+            open_search: None,
+        }
+    }
+}
+
+/// 快捷键对应的操作
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShortcutAction {
+    Toggle,
+    PastePlain,
+    /// 从 1 开始
+    PasteNth(usize),
+    PauseCapture,
+    OpenSearch,
+}
+
+impl fmt::Display for ShortcutAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShortcutAction::Toggle => write!(f, "toggle"),
+            ShortcutAction::PastePlain => write!(f, "pastePlain"),
+            ShortcutAction::PasteNth(n) => write!(f, "pasteNth.{}", n),
+            ShortcutAction::PauseCapture => write!(f, "pauseCapture"),
+            ShortcutAction::OpenSearch => write!(f, "openSearch"),
+        }
+    }
+}
+
+/// 注册失败的快捷键，通常是被其他应用占用了
+#[derive(Serialize, Clone, Debug, TS)]
+#[ts(export, export_to = "../../src/bindings/")]
+pub struct ShortcutFailure {
+    pub action: String,
+    pub accelerator: String,
+    pub reason: String,
+}
+
+impl ShortcutSettings {
+    /// 设置了的快捷键和对应的操作
+    pub fn bindings(&self) -> Vec<(ShortcutAction, &str)> {
+        let mut bindings = vec![(ShortcutAction::Toggle, &self.toggle), (ShortcutAction::PastePlain, &self.paste_plain)];
+        bindings.extend(self.paste_nth.iter().enumerate().map(|(i, accelerator)| (ShortcutAction::PasteNth(i + 1), accelerator)));
+        bindings.push((ShortcutAction::PauseCapture, &self.pause_capture));
+        bindings.push((ShortcutAction::OpenSearch, &self.open_search));
+        bindings.into_iter()
+            .filter_map(|(action, accelerator)| Some((action, accelerator.as_deref()?)))
+            .filter(|(_, accelerator)| !accelerator.trim().is_empty())
+            .collect()
+    }
+
+    /// 检查快捷键的格式，以及有没有多个操作使用了同一个快捷键
+    pub fn validate(&self) -> Result<(), String> {
+        if self.paste_nth.len() > MAX_PASTE_NTH {
+            return Err(format!("at most {} paste shortcuts are supported", MAX_PASTE_NTH));
+        }
+        let mut used: HashMap<String, ShortcutAction> = HashMap::new();
+        for (action, accelerator) in self.bindings() {
+            let normalized = normalize(accelerator).map_err(|err| format!("{}: {}", action, err))?;
+            if let Some(other) = used.insert(normalized, action) {
+                return Err(format!("{} and {} both use {}", other, action, accelerator));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 统一修饰键的写法和顺序，用于检查格式和比较是否冲突
+fn normalize(accelerator: &str) -> Result<String, String> {
+    let parts: Vec<String> = accelerator.split('+').map(|part| part.trim().to_lowercase()).collect();
+    let (key, modifiers) = parts.split_last().ok_or_else(|| "empty shortcut".to_string())?;
+    let mut normalized = BTreeSet::new();
+    for modifier in modifiers {
+        normalized.insert(match modifier.as_str() {
+            "shift" => "shift",
+            "ctrl" | "control" => "ctrl",
+            "alt" | "option" => "alt",
+            "cmd" | "command" | "super" | "meta" => "super",
+            "commandorcontrol" | "commandorctrl" | "cmdorctrl" | "cmdorcontrol" => {
+                if cfg!(target_os = "macos") { "super" } else { "ctrl" }
+            }
+            _ => return Err(format!("unknown modifier {:?}", modifier)),
+        });
+    }
+    let is_function_key = key.strip_prefix('f')
+        .and_then(|n| n.parse::<usize>().ok())
+        .is_some_and(|n| (1..=FUNCTION_KEYS).contains(&n));
+    let is_char_key = key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric());
+    if !is_function_key && !is_char_key && !NAMED_KEYS.contains(&key.as_str()) {
+        return Err(format!("unknown key {:?}", key));
+    }
+    if normalized.is_empty() && !is_function_key {
+        return Err("a modifier is required".to_string());
+    }
+    Ok(normalized.into_iter().chain([key.as_str()]).collect::<Vec<_>>().join("+"))
+}
+
+/// 当前的快捷键设置、已注册的快捷键和注册失败的快捷键，设置保存在 Settings 中
+pub struct ShortcutState {
+    settings: Mutex<ShortcutSettings>,
+    registered: Mutex<Vec<String>>,
+    failures: Mutex<Vec<ShortcutFailure>>,
+}
+
+impl ShortcutState {
+    pub fn new(settings: ShortcutSettings) -> ShortcutState {
+        ShortcutState {
+            settings: Mutex::new(settings),
+            registered: Mutex::new(vec![]),
+            failures: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn settings(&self) -> ShortcutSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn failures(&self) -> Vec<ShortcutFailure> {
+        self.failures.lock().unwrap().clone()
+    }
+
+    /// 替换设置，调用 register_all 后生效
+    pub fn set_settings(&self, settings: ShortcutSettings) {
+        *self.settings.lock().unwrap() = settings;
+    }
+}
+
+/// 注销之前注册的快捷键，按当前设置重新注册，返回注册失败的快捷键并通知前端
+pub fn register_all(app: &AppHandle) -> Vec<ShortcutFailure> {
+    let state = app.state::<ShortcutState>();
+    let settings = state.settings();
+    let mut manager = app.global_shortcut_manager();
+    let mut registered = state.registered.lock().unwrap();
+    for accelerator in registered.drain(..) {
+        if let Err(err) = manager.unregister(&accelerator) {
+            log::warn!("failed to unregister shortcut {}: {}", accelerator, err);
+        }
+    }
+
+    let mut failures = vec![];
+    for (action, accelerator) in settings.bindings() {
+        let app_handle = app.clone();
+        match manager.register(accelerator, move || run(&app_handle, action)) {
+            Ok(()) => registered.push(accelerator.to_string()),
+            Err(err) => {
+                log::warn!("failed to register shortcut {} for {}: {}", accelerator, action, err);
+                failures.push(ShortcutFailure {
+                    action: action.to_string(),
+                    accelerator: accelerator.to_string(),
+                    reason: err.to_string(),
+                });
+            }
+        }
+    }
+    *state.failures.lock().unwrap() = failures.clone();
+    if !failures.is_empty() {
+        if let Err(err) = app.emit_all(Topic::SHORTCUT_FAILURE, &failures) {
+            log::error!("failed to emit shortcut failures: {}", err);
+        }
+    }
+    failures
+}
+
+fn run(app: &AppHandle, action: ShortcutAction) {
+    log::debug!("shortcut {} triggered", action);
+    match action {
+        ShortcutAction::Toggle => toggle_window(app),
+        ShortcutAction::OpenSearch => {
+            show_window(app);
+            if let Err(err) = app.emit_all(Topic::OPEN_SEARCH, ()) {
+                log::error!("failed to emit open search: {}", err);
+            }
+        }
+        ShortcutAction::PauseCapture => {
+            let status = app.state::<ClipboardManager>().inner().toggle_paused();
+            log::info!("clipboard capture {}", if status.paused { "paused" } else { "resumed" });
+            if let Err(err) = app.emit_all(Topic::LISTENER_STATUS, status) {
+                log::error!("failed to emit listener status: {}", err);
+            }
+        }
+        // 粘贴要等焦点切换，不能阻塞快捷键所在的主线程
+        ShortcutAction::PastePlain => {
+            let app = app.clone();
+            thread::spawn(move || if let Err(err) = paste_plain(&app) {
+                log::error!("failed to paste plain text: {}", err);
+            });
+        }
+        ShortcutAction::PasteNth(n) => {
+            let app = app.clone();
+            thread::spawn(move || if let Err(err) = paste_nth(&app, n) {
+                log::error!("failed to paste record {}: {}", n, err);
+            });
+        }
+    }
+}
+
+fn toggle_window(app: &AppHandle) {
+    let visible = match app.get_window("main").map(|window| window.is_visible()) {
+        Some(Ok(visible)) => visible,
+        Some(Err(err)) => {
+            log::error!("failed to get main window visibility: {}", err);
+            return;
+        }
+        None => {
+            log::error!("main window not found");
+            return;
+        }
+    };
+    if !visible {
+        show_window(app);
+    } else if let Err(err) = cmd::hide_window(app) {
+        log::error!("failed to hide main window: {}", err);
+    }
+}
+
+fn show_window(app: &AppHandle) {
+    let window = match app.get_window("main") {
+        Some(window) => window,
+        None => {
+            log::error!("main window not found");
+            return;
+        }
+    };
+    // 每次显示时重新计算位置，抽屉出现在光标所在的屏幕上
+    placement::place_main_window(app);
+    // 和 cmd::hide_window 对应，macOS 上显示整个应用
+    #[cfg(target_os = "macos")]
+    let shown = app.show();
+    #[cfg(not(target_os = "macos"))]
+    let shown = window.show();
+    if let Err(err) = shown.and_then(|_| window.set_focus()) {
+        log::error!("failed to show main window: {}", err);
+    }
+}
+
+/// 只保留剪贴板上的文本再粘贴
+fn paste_plain(app: &AppHandle) -> Result<(), String> {
+    let text = app.state::<ClipboardManager>().inner().get_text()
+        .ok_or_else(|| "no text on clipboard".to_string())?;
+    let content = ClipboardContent {
+        main_format: ClipboardFormatContent::Text(text.clone()),
+        data: vec![ClipboardFormatContent::Text(text)],
+    };
+    cmd::paste_content(app, content, None, PasteMode::Paste, false).map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// 不打开窗口，直接粘贴剪贴板历史中最近的第 n 条记录
+fn paste_nth(app: &AppHandle, n: usize) -> Result<(), String> {
+    let records = app.state::<StorageConn>().inner().latest_records(n).map_err(|err| err.to_string())?;
+    let record = records.get(n - 1).ok_or_else(|| format!("history has only {} records", records.len()))?;
+    cmd::paste(app.clone(), record.id, None).map(|_| ()).map_err(|err| err.to_string())
+}
@@ -1,22 +1,32 @@
-use std::{path::{Path, PathBuf}, sync::{Mutex, mpsc::{self, Receiver, Sender}, Arc}, thread, time, fmt, result, rc::Rc};
+use std::{path::{Path, PathBuf}, sync::{Mutex, mpsc::{self, Receiver, Sender}, Arc, atomic::{AtomicUsize, Ordering}}, thread, time, fmt, result, rc::Rc, fs, io};
 
-use chrono::Local;
-use rusqlite::{Connection, params, Row, types::Value};
-use serde::Serialize;
-use tauri::{AppHandle, Manager};
+use chrono::{Local, DateTime};
+use rusqlite::{Connection, params, Row, types::Value, DatabaseName, OpenFlags, OptionalExtension, Transaction, backup::Progress};
+use serde::{Serialize, Deserialize};
 
-use crate::{clipboard::{ClipboardContent, ClipboardFormatContent}, event::Topic};
+use crate::{content::{ClipboardContent, ClipboardFormatContent}, event::{Topic, EventSink}, i18n, linked::LinkedLibrary};
 
 /// 包装来自上游的错误
 #[derive(Debug)]
 pub enum StorageError {
     Serde(serde_json::Error),
-    Sqlite(rusqlite::Error)
+    Sqlite(rusqlite::Error),
+    Io(io::Error),
+    /// 参数不合法，如把标签移动到自己的子标签下
+    Invalid(String),
+    /// 关联目录中的记录只读
+    LinkedReadOnly,
 }
 
 impl fmt::Display for StorageError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            StorageError::Serde(err) => write!(f, "serde error: {}", err),
+            StorageError::Sqlite(err) => write!(f, "sqlite error: {}", err),
+            StorageError::Io(err) => write!(f, "io error: {}", err),
+            StorageError::Invalid(msg) => write!(f, "{}", msg),
+            StorageError::LinkedReadOnly => write!(f, "linked records are read-only"),
+        }
     }
 }
 
@@ -34,6 +44,12 @@ impl From<rusqlite::Error> for StorageError {
     }
 }
 
+impl From<io::Error> for StorageError {
+    fn from(value: io::Error) -> Self {
+        StorageError::Io(value)
+    }
+}
+
 pub type Result<T> = result::Result<T, StorageError>;
 
 /// 剪贴板记录
@@ -44,6 +60,11 @@ pub struct ClipboardRecord {
     pub data: String,
     pub content_type: String,
     pub time: i64,
+    /// 关联目录中片段的名称，本地记录为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// 密码等敏感内容，粘贴后会定时清除剪贴板
+    pub secret: bool,
 }
 
 /// ClipboardContent 转 ClipboardRecord
@@ -62,6 +83,8 @@ impl TryFrom<ClipboardContent> for ClipboardRecord {
             main_data: serde_json::to_string(&(value.main_format.clone()))?,
             data: serde_json::to_string(&value)?,
             time: Local::now().timestamp_millis(),
+            name: None,
+            secret: false,
         })
     }
 }
@@ -70,7 +93,7 @@ impl TryFrom<ClipboardRecord> for ClipboardContent {
     type Error = serde_json::Error;
 
     fn try_from(record: ClipboardRecord) -> result::Result<Self, Self::Error> {
-        Ok(serde_json::from_str(record.data.as_str())?)
+        serde_json::from_str(record.data.as_str())
     }
 }
 
@@ -82,6 +105,8 @@ impl ClipboardRecord {
             data: row.get(2)?,
             content_type: row.get(3)?,
             time: row.get(4)?,
+            name: None,
+            secret: row.get("secret")?,
         })
     }
 }
@@ -89,51 +114,353 @@ impl ClipboardRecord {
 /// 标签记录
 #[derive(Serialize, Clone, Debug)]
 pub struct TagRecord {
-    id: i64,
-    name: String
+    pub id: i64,
+    pub name: String,
+    pub sort_order: i64,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub parent_id: Option<i64>,
+    /// 关联的目录，目录中的文件作为该标签下的只读记录
+    pub linked_dir: Option<String>,
 }
 
 impl TagRecord {
+    const COLUMNS: &'static str = "id, name, sort_order, color, icon, parent_id, linked_dir";
+
     fn parse(row: &Row) -> result::Result<TagRecord, rusqlite::Error> {
         Ok(TagRecord {
             id: row.get(0)?,
             name: row.get(1)?,
+            sort_order: row.get(2)?,
+            color: row.get(3)?,
+            icon: row.get(4)?,
+            parent_id: row.get(5)?,
+            linked_dir: row.get(6)?,
         })
     }
 }
 
+/// 数据库迁移脚本，下标 + 1 即为执行后的 user_version
+const MIGRATIONS: &[&str] = &[
+    // v1: 标签支持手动排序、颜色和图标
+    "
+    ALTER TABLE tags ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE tags ADD COLUMN color TEXT;
+    ALTER TABLE tags ADD COLUMN icon TEXT;
+    UPDATE tags SET sort_order = id;
+    ",
+    // v2: 标签支持嵌套，parent_id 为 NULL 表示顶层标签
+    "
+    ALTER TABLE tags ADD COLUMN parent_id INTEGER;
+    CREATE INDEX IF NOT EXISTS idx_parent_id ON tags (parent_id);
+    ",
+    // v3: 标签内的记录支持手动排序
+    "
+    ALTER TABLE clipboard_tags ADD COLUMN position REAL NOT NULL DEFAULT 0;
+    UPDATE clipboard_tags SET position = id * 1024.0;
+    ",
+    // v4: 局域网同步。标签和记录增加全局唯一的 uid，
+    // 由触发器把本地对标签及标签下记录（不含剪贴板历史）的修改写入 sync_log，
+    // sync_log 中每个实体只保留最新的一条变更，用 Lamport 时钟 (clock, node) 判断新旧
+    "
+    ALTER TABLE tags ADD COLUMN uid TEXT;
+    ALTER TABLE clipboard ADD COLUMN uid TEXT;
+    UPDATE tags SET uid = lower(hex(randomblob(16))) WHERE uid IS NULL;
+    UPDATE clipboard SET uid = lower(hex(randomblob(16))) WHERE uid IS NULL;
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_tags_uid ON tags (uid);
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_clipboard_uid ON clipboard (uid);
+
+    CREATE TABLE IF NOT EXISTS sync_state (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        node TEXT NOT NULL,
+        clock INTEGER NOT NULL DEFAULT 0,
+        seq INTEGER NOT NULL DEFAULT 0,
+        applying INTEGER NOT NULL DEFAULT 0
+    );
+    INSERT OR IGNORE INTO sync_state (id, node) VALUES (0, lower(hex(randomblob(8))));
+    CREATE TABLE IF NOT EXISTS sync_log (
+        entity TEXT NOT NULL,
+        uid TEXT NOT NULL,
+        clock INTEGER NOT NULL,
+        node TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        deleted INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (entity, uid)
+    );
+    CREATE INDEX IF NOT EXISTS idx_sync_log_seq ON sync_log (seq);
+    CREATE TABLE IF NOT EXISTS sync_peers (
+        node TEXT PRIMARY KEY,
+        last_seq INTEGER NOT NULL DEFAULT 0
+    );
+
+    UPDATE sync_state SET clock = clock + 1, seq = seq + 1;
+    INSERT INTO sync_log (entity, uid, clock, node, seq)
+        SELECT 'tag', t.uid, s.clock, s.node, s.seq FROM tags t, sync_state s WHERE t.id != 0;
+    INSERT INTO sync_log (entity, uid, clock, node, seq)
+        SELECT 'record', c.uid, s.clock, s.node, s.seq FROM clipboard c, sync_state s
+        WHERE EXISTS(SELECT 1 FROM clipboard_tags ct WHERE ct.clipboard_id = c.id AND ct.tag_id != 0);
+
+    CREATE TRIGGER IF NOT EXISTS sync_tags_insert AFTER INSERT ON tags BEGIN
+        UPDATE tags SET uid = lower(hex(randomblob(16))) WHERE id = NEW.id AND uid IS NULL;
+        UPDATE sync_state SET clock = clock + 1, seq = seq + 1 WHERE applying = 0 AND NEW.id != 0;
+        INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+            SELECT 'tag', t.uid, s.clock, s.node, s.seq, 0 FROM tags t, sync_state s
+            WHERE t.id = NEW.id AND s.applying = 0 AND NEW.id != 0;
+    END;
+    CREATE TRIGGER IF NOT EXISTS sync_tags_update AFTER UPDATE OF name, color, icon, parent_id, sort_order ON tags
+    WHEN NEW.id != 0 AND (SELECT applying FROM sync_state) = 0 BEGIN
+        UPDATE sync_state SET clock = clock + 1, seq = seq + 1;
+        INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+            SELECT 'tag', NEW.uid, clock, node, seq, 0 FROM sync_state;
+    END;
+    CREATE TRIGGER IF NOT EXISTS sync_tags_delete AFTER DELETE ON tags
+    WHEN OLD.id != 0 AND (SELECT applying FROM sync_state) = 0 BEGIN
+        UPDATE sync_state SET clock = clock + 1, seq = seq + 1;
+        INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+            SELECT 'tag', OLD.uid, clock, node, seq, 1 FROM sync_state;
+    END;
+    CREATE TRIGGER IF NOT EXISTS sync_clipboard_insert AFTER INSERT ON clipboard BEGIN
+        UPDATE clipboard SET uid = lower(hex(randomblob(16))) WHERE id = NEW.id AND uid IS NULL;
+    END;
+    CREATE TRIGGER IF NOT EXISTS sync_clipboard_delete AFTER DELETE ON clipboard
+    WHEN (SELECT applying FROM sync_state) = 0
+        AND EXISTS(SELECT 1 FROM clipboard_tags WHERE clipboard_id = OLD.id AND tag_id != 0) BEGIN
+        UPDATE sync_state SET clock = clock + 1, seq = seq + 1;
+        INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+            SELECT 'record', OLD.uid, clock, node, seq, 1 FROM sync_state;
+    END;
+    CREATE TRIGGER IF NOT EXISTS sync_clipboard_tags_insert AFTER INSERT ON clipboard_tags
+    WHEN NEW.tag_id != 0 AND (SELECT applying FROM sync_state) = 0 BEGIN
+        UPDATE sync_state SET clock = clock + 1, seq = seq + 1;
+        INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+            SELECT 'record', c.uid, s.clock, s.node, s.seq, 0 FROM clipboard c, sync_state s
+            WHERE c.id = NEW.clipboard_id;
+    END;
+    CREATE TRIGGER IF NOT EXISTS sync_clipboard_tags_update AFTER UPDATE OF tag_id, position ON clipboard_tags
+    WHEN NEW.tag_id != 0 AND (SELECT applying FROM sync_state) = 0 BEGIN
+        UPDATE sync_state SET clock = clock + 1, seq = seq + 1;
+        INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+            SELECT 'record', c.uid, s.clock, s.node, s.seq, 0 FROM clipboard c, sync_state s
+            WHERE c.id = NEW.clipboard_id;
+    END;
+    ",
+    // v5: 标签可以关联一个目录，作为共享的片段库
+    "
+    ALTER TABLE tags ADD COLUMN linked_dir TEXT;
+    ",
+    // v6: 记录可以标记为敏感内容
+    "
+    ALTER TABLE clipboard ADD COLUMN secret INTEGER NOT NULL DEFAULT 0;
+    ",
+    // v7: 按标签和位置查询最近的记录
+    "
+    CREATE INDEX IF NOT EXISTS idx_tid_position ON clipboard_tags (tag_id, position);
+    ",
+    // v8: 标记敏感内容也要同步给其他设备
+    "
+    CREATE TRIGGER IF NOT EXISTS sync_clipboard_secret_update AFTER UPDATE OF secret ON clipboard
+    WHEN (SELECT applying FROM sync_state) = 0
+        AND EXISTS(SELECT 1 FROM clipboard_tags WHERE clipboard_id = NEW.id AND tag_id != 0) BEGIN
+        UPDATE sync_state SET clock = clock + 1, seq = seq + 1;
+        INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+            SELECT 'record', NEW.uid, clock, node, seq, 0 FROM sync_state;
+    END;
+    ",
+];
+
+/// 标签内相邻记录 position 的默认间隔，移动记录时取前后两条的中间值
+const POSITION_GAP: f64 = 1024.0;
+
+/// 同步的实体类型
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncEntity {
+    Tag,
+    Record,
+}
+
+impl SyncEntity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SyncEntity::Tag => "tag",
+            SyncEntity::Record => "record",
+        }
+    }
+}
+
+/// 同步时交换的一条变更，deleted 为 false 时带上实体当前的内容
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncChange {
+    pub entity: SyncEntity,
+    pub uid: String,
+    pub clock: i64,
+    pub node: String,
+    pub deleted: bool,
+    pub tag: Option<SyncTag>,
+    pub record: Option<SyncRecord>,
+}
+
+impl SyncChange {
+    /// Lamport 时钟相同时用节点 id 决胜
+    fn is_newer_than(&self, clock: i64, node: &str) -> bool {
+        (self.clock, self.node.as_str()) > (clock, node)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncTag {
+    pub name: String,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub parent_uid: Option<String>,
+    pub sort_order: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SyncRecord {
+    pub tag_uid: String,
+    pub position: f64,
+    pub main_data: String,
+    pub data: String,
+    pub content_type: String,
+    pub time: i64,
+    /// 旧版本的设备不会发送这个字段
+    #[serde(default)]
+    pub secret: bool,
+}
+
+/// 递归查询标签 ?1 及其所有子孙标签的 id，用 UNION 去重以防出现环时无限递归
+const SUB_TAGS_CTE: &str = "
+    WITH RECURSIVE sub_tags(id) AS (
+        SELECT ?1
+        UNION
+        SELECT t.id FROM tags t INNER JOIN sub_tags s ON t.parent_id = s.id
+    )
+";
+
+/// 备份保留的份数
+const BACKUP_GENERATIONS: usize = 5;
+/// 自动备份的间隔
+const BACKUP_INTERVAL: time::Duration = time::Duration::from_secs(6 * 60 * 60);
+
+/// 数据库备份
+#[derive(Serialize, Clone, Debug)]
+pub struct BackupInfo {
+    pub name: String,
+    pub size: u64,
+    pub time: i64,
+}
+
+/// 数据库统计信息，用于诊断
+#[derive(Serialize, Clone, Debug)]
+pub struct StorageStats {
+    pub schema_version: i64,
+    pub tags: i64,
+    pub linked_tags: i64,
+    pub records: i64,
+    pub history_records: i64,
+    pub sync_log: i64,
+    pub page_count: i64,
+    pub page_size: i64,
+}
+
+/// GC 的配置，运行中可以通过 set_gc_config 修改
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GcConfig {
+    /// 剪贴板历史保留的条数
+    pub history_limit: usize,
+    /// 两次 GC 之间的间隔
+    pub interval: time::Duration,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            history_limit: 100,
+            interval: time::Duration::from_secs(10),
+        }
+    }
+}
+
 /// 数据库连接
-#[derive(Debug)]
 pub struct StorageConn {
-    app_handler: AppHandle,
+    sink: Option<Arc<dyn EventSink>>,
     db: Arc<Mutex<Connection>>,
+    backup_dir: PathBuf,
+    gc_config: Arc<Mutex<GcConfig>>,
     gc_close_handle: Mutex<Sender<()>>,
+    linked: LinkedLibrary,
 }
 
 impl Drop for StorageConn {
     fn drop(&mut self) {
-        self.gc_close_handle.lock().unwrap().send(()).unwrap();
+        // 没有启动GC时接收端已经关闭，忽略发送失败
+        let _ = self.gc_close_handle.lock().unwrap().send(());
     }
 }
 
 impl StorageConn {
-    pub fn new(data_dir: &PathBuf, app: AppHandle) -> Result<StorageConn> {
-        let db_dir = Path::join(data_dir.as_path(), "drawer.db");
-        let db = Connection::open(db_dir)?; 
+    /// 打开数据库并按 gc_config 启动GC，数据变更通过 sink 通知出去
+    pub fn new<S: EventSink>(data_dir: &Path, gc_config: GcConfig, sink: S) -> Result<StorageConn> {
         let (tx, rx) = mpsc::channel();
+        let mut conn = Self::open_file(data_dir)?;
+        let sink: Arc<dyn EventSink> = Arc::new(sink);
+        conn.sink = Some(sink.clone());
+        conn.gc_config = Arc::new(Mutex::new(gc_config));
+        conn.gc_close_handle = Mutex::new(tx);
+        conn.gc_launch(rx);
+        conn.linked.set_listener(move |tag_id| sink.emit(Topic::LINKED_TAG_UPDATE, tag_id.into()));
+        conn.linked.set_watching(true);
+        conn.link_dirs()?;
+        Ok(conn)
+    }
+
+    /// 只打开数据库，不启动GC也不发送通知，关联目录只读取一次不监听，供命令行等没有界面的场景使用
+    pub fn open(data_dir: &Path) -> Result<StorageConn> {
+        let conn = Self::open_file(data_dir)?;
+        conn.link_dirs()?;
+        Ok(conn)
+    }
+
+    fn open_file(data_dir: &Path) -> Result<StorageConn> {
+        Self::open_with(Connection::open(data_dir.join("drawer.db"))?, data_dir.join("backups"))
+    }
+
+    /// 打开一个内存数据库，不启动GC，供测试使用，每个连接的备份目录各不相同
+    pub fn open_in_memory() -> Result<StorageConn> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("drawer-backups-{}-{}", std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst));
+        Self::open_with(Connection::open_in_memory()?, std::env::temp_dir().join(name))
+    }
+
+    fn open_with(db: Connection, backup_dir: PathBuf) -> Result<StorageConn> {
+        let (tx, _) = mpsc::channel();
         let conn = StorageConn { 
-            app_handler: app,
+            sink: None,
             db: Arc::new(Mutex::new(db)),
+            backup_dir,
+            gc_config: Arc::new(Mutex::new(GcConfig::default())),
             gc_close_handle: Mutex::new(tx),
+            linked: LinkedLibrary::default(),
         };
         {
             rusqlite::vtab::array::load_module(&conn.db.clone().lock().unwrap())?;
         }
         conn.init_table().expect("failed to initialization database table");
-        conn.gc_launch(rx);
+        conn.migrate(None).expect("failed to migrate database");
         Ok(conn)
     }
 
+    /// 加载所有关联标签的目录
+    fn link_dirs(&self) -> Result<()> {
+        for tag in self.get_tags()? {
+            if let Some(dir) = &tag.linked_dir {
+                self.linked.link(tag.id, Path::new(dir));
+            }
+        }
+        Ok(())
+    }
+
     /// 初始化表结构
     fn init_table(&self) -> Result<()> {
         let db = self.db.lock().unwrap();
@@ -143,7 +470,7 @@ impl StorageConn {
                 name TEXT
             );
         ", ())?;
-        db.execute("INSERT OR IGNORE INTO tags (id, name) VALUES (0, '📝 剪贴板历史');", ())?;
+        db.execute("INSERT OR IGNORE INTO tags (id, name) VALUES (0, ?1);", params![i18n::t("history-tag")])?;
         db.execute("
             CREATE TABLE IF NOT EXISTS clipboard (
                 id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
@@ -166,32 +493,191 @@ impl StorageConn {
         Ok(())
     }
 
-    /// 启动GC，定期删除过期数据
+    /// 执行尚未执行过的迁移脚本，版本号记录在 user_version 中，keep 为轮换备份时保留的备份
+    fn migrate(&self, keep: Option<&str>) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let version: i64 = db.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+        let has_data: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM clipboard) OR (SELECT COUNT(*) FROM tags) > 1;", [], |row| row.get(0))?;
+        // 迁移前先备份，新建的空数据库不需要
+        if (version as usize) < MIGRATIONS.len() && has_data {
+            Self::backup_inner(&db, &self.backup_dir, &format!("migration-v{}", version))?;
+            Self::rotate_backups(&self.backup_dir, keep)?;
+        }
+        for (i, sql) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = db.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// 启动GC，定期删除过期数据并备份数据库
     fn gc_launch(&self, close_handle: Receiver<()>) { 
         let db = self.db.clone();
-        let app_handler = self.app_handler.app_handle();
+        let sink = self.sink.clone();
+        let backup_dir = self.backup_dir.clone();
+        let gc_config = self.gc_config.clone();
+        // 距离上次备份已经超过间隔时，启动后立即备份
+        let mut last_backup = match Self::list_backups_inner(&backup_dir) {
+            Ok(backups) => backups.first()
+                .map(|b| time::Duration::from_millis(Local::now().timestamp_millis().saturating_sub(b.time) as u64))
+                .and_then(|age| time::Instant::now().checked_sub(age)),
+            Err(_) => None,
+        };
         thread::spawn(move || loop {
-            // drop 时退出线程
-            match close_handle.try_recv() {
-                Ok(()) | Err(mpsc::TryRecvError::Disconnected) => {
-                    break;
+            // 剪贴板历史只保留 history_limit 条
+            let config = *gc_config.lock().unwrap();
+            match Self::delete_outdated_record(db.clone(), config.history_limit) {
+                Ok(ids) => {
+                    if let Some(sink) = &sink {
+                        sink.emit(Topic::CLIPBOARD_DELETE, ids.into());
+                    }
                 },
-                _ => {}
+                Err(err) => log::error!("failed to delete outdated record: {}", err)
             }
 
-            // 剪贴板历史只保留100条
-            match Self::delete_outdated_record(db.clone(), 100) {
-                Ok(ids) => {
-                    // TODO: 这里通知应该用回调抽出去，依赖进来个app handler不太好
-                    app_handler.emit_all(Topic::CLIPBOARD_DELETE, ids).unwrap();
-                },
-                Err(err) => println!("failed to delete outdated record: {}", err.to_string())
+            if last_backup.map_or(true, |t| t.elapsed() >= BACKUP_INTERVAL) {
+                let result = Self::backup_inner(&db.lock().unwrap(), &backup_dir, "auto")
+                    .and_then(|_| Self::rotate_backups(&backup_dir, None));
+                if let Err(err) = result {
+                    log::error!("failed to backup database: {}", err);
+                }
+                last_backup = Some(time::Instant::now());
             }
 
-            thread::sleep(time::Duration::from_secs(10));
+            // 等待下一次 GC，drop 时立即退出线程
+            match close_handle.recv_timeout(config.interval) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
         });
     }
 
+    /// 修改GC的配置，下一次GC时生效
+    pub fn set_gc_config(&self, config: GcConfig) {
+        *self.gc_config.lock().unwrap() = config;
+    }
+
+    /// 用 SQLite 在线备份接口备份数据库，并校验备份的完整性
+    fn backup_inner(db: &Connection, backup_dir: &Path, reason: &str) -> Result<BackupInfo> {
+        fs::create_dir_all(backup_dir)?;
+        let name = format!("drawer-{}-{}.db", Local::now().format("%Y%m%d-%H%M%S%3f"), reason);
+        let path = backup_dir.join(&name);
+        db.backup(DatabaseName::Main, &path, None)?;
+        if let Err(err) = Self::check_integrity(&path) {
+            let _ = fs::remove_file(&path);
+            return Err(err);
+        }
+        Self::backup_info(&path)
+    }
+
+    /// 执行 PRAGMA integrity_check 检查数据库文件是否损坏
+    fn check_integrity(path: &Path) -> Result<()> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let result: String = conn.query_row("PRAGMA integrity_check;", [], |row| row.get(0))?;
+        if result != "ok" {
+            return Err(StorageError::Invalid(format!("{} is corrupted: {}", path.display(), result)));
+        }
+        Ok(())
+    }
+
+    fn backup_info(path: &Path) -> Result<BackupInfo> {
+        let metadata = fs::metadata(path)?;
+        Ok(BackupInfo {
+            name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            size: metadata.len(),
+            time: DateTime::<Local>::from(metadata.modified()?).timestamp_millis(),
+        })
+    }
+
+    /// 按时间从新到旧列出备份
+    fn list_backups_inner(backup_dir: &Path) -> Result<Vec<BackupInfo>> {
+        let mut ret = Vec::new();
+        if !backup_dir.exists() {
+            return Ok(ret);
+        }
+        for entry in fs::read_dir(backup_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "db") {
+                ret.push(Self::backup_info(&path)?);
+            }
+        }
+        // 文件名以时间开头，按文件名排序即按时间排序
+        ret.sort_by(|a, b| b.name.cmp(&a.name));
+        Ok(ret)
+    }
+
+    /// 只保留最新的几份备份，keep 不计入份数也不会被删除
+    fn rotate_backups(backup_dir: &Path, keep: Option<&str>) -> Result<()> {
+        let backups = Self::list_backups_inner(backup_dir)?;
+        for backup in backups.iter().filter(|b| Some(b.name.as_str()) != keep).skip(BACKUP_GENERATIONS) {
+            fs::remove_file(backup_dir.join(&backup.name))?;
+        }
+        Ok(())
+    }
+
+    /// 立即备份数据库
+    pub fn backup(&self) -> Result<BackupInfo> {
+        let info = Self::backup_inner(&self.db.lock().unwrap(), &self.backup_dir, "manual")?;
+        Self::rotate_backups(&self.backup_dir, None)?;
+        Ok(info)
+    }
+
+    /// 获取所有备份
+    pub fn list_backups(&self) -> Result<Vec<BackupInfo>> {
+        Self::list_backups_inner(&self.backup_dir)
+    }
+
+    /// 从备份恢复数据库，恢复前会先备份当前数据库，恢复后通知界面重新加载
+    pub fn restore_backup(&self, name: &str) -> Result<()> {
+        // 只允许恢复备份目录下的文件
+        if !self.list_backups()?.iter().any(|b| b.name == name) {
+            return Err(StorageError::Invalid(format!("backup {} not found", name)));
+        }
+        let path = self.backup_dir.join(name);
+        Self::check_integrity(&path)?;
+        {
+            let mut db = self.db.lock().unwrap();
+            Self::backup_inner(&db, &self.backup_dir, "pre-restore")?;
+            db.restore(DatabaseName::Main, &path, None::<fn(Progress)>)?;
+        }
+        // 备份可能来自旧版本
+        self.migrate(Some(name))?;
+        {
+            // 恢复后本地的 seq 回退了，换一个节点 id，其他设备会从头拉取本机的变更；
+            // 从其他设备同步到的进度也一并清零，重新拉取备份之后的变更
+            let db = self.db.lock().unwrap();
+            db.execute_batch("
+                UPDATE sync_state SET node = lower(hex(randomblob(8))), applying = 0;
+                UPDATE sync_peers SET last_seq = 0;
+            ")?;
+        }
+        // 恢复的备份可能是最旧的一份，轮换时保留
+        Self::rotate_backups(&self.backup_dir, Some(name))?;
+        if let Some(sink) = &self.sink {
+            sink.emit(Topic::STORAGE_RELOAD, serde_json::Value::Null);
+        }
+        Ok(())
+    }
+
+    /// 获取数据库的统计信息
+    pub fn stats(&self) -> Result<StorageStats> {
+        let db = self.db.lock().unwrap();
+        let count = |sql: &str| db.query_row(sql, [], |row| row.get::<_, i64>(0));
+        Ok(StorageStats {
+            schema_version: count("PRAGMA user_version;")?,
+            tags: count("SELECT COUNT(*) FROM tags;")?,
+            linked_tags: count("SELECT COUNT(*) FROM tags WHERE linked_dir IS NOT NULL;")?,
+            records: count("SELECT COUNT(*) FROM clipboard;")?,
+            history_records: count("SELECT COUNT(*) FROM clipboard_tags WHERE tag_id = 0;")?,
+            sync_log: count("SELECT COUNT(*) FROM sync_log;")?,
+            page_count: count("PRAGMA page_count;")?,
+            page_size: count("PRAGMA page_size;")?,
+        })
+    }
+
     /// 只保留n条数据，删除剩余的
     fn delete_outdated_record(_db: Arc<Mutex<Connection>>, n: usize) -> Result<Vec<i64>> {
         // 先获取要被删除的记录 返回用
@@ -228,8 +714,12 @@ impl StorageConn {
 
     /// 获取所有标签
     pub fn get_tags(&self) -> Result<Vec<TagRecord>> {
-        let db = self.db.lock().unwrap();
-        let mut stmt = db.prepare("SELECT * FROM tags;")?;
+        Self::get_tags_inner(&self.db.lock().unwrap())
+    }
+
+    fn get_tags_inner(db: &Connection) -> Result<Vec<TagRecord>> {
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM tags ORDER BY sort_order, id;", TagRecord::COLUMNS))?;
         let rows = stmt.query_map(params![], TagRecord::parse)?;
         let mut ret = Vec::new();
         for r in rows {
@@ -238,46 +728,253 @@ impl StorageConn {
         Ok(ret)
     }
 
-    /// 创建一个标签
-    pub fn insert_tag(&self, name: String) -> Result<TagRecord> {
+    /// 获取某个标签的所有子孙标签（不含自身）
+    pub fn get_descendant_tags(&self, id: i64) -> Result<Vec<TagRecord>> {
         let db = self.db.lock().unwrap();
-        db.execute("INSERT INTO tags (name) VALUES (?1);", params![name])?;
+        let mut stmt = db.prepare(&format!("
+            {} SELECT {} FROM tags WHERE id IN (SELECT id FROM sub_tags) AND id != ?1
+            ORDER BY sort_order, id;
+        ", SUB_TAGS_CTE, TagRecord::COLUMNS))?;
+        let rows = stmt.query_map(params![id], TagRecord::parse)?;
+        let mut ret = Vec::new();
+        for r in rows {
+            ret.push(r?);
+        }
+        Ok(ret)
+    }
+
+    /// 创建一个标签，parent_id 为 None 时创建在顶层
+    pub fn insert_tag(&self, name: String, parent_id: Option<i64>) -> Result<TagRecord> {
+        Self::insert_tag_inner(&self.db.lock().unwrap(), name, parent_id)
+    }
+
+    fn insert_tag_inner(db: &Connection, name: String, parent_id: Option<i64>) -> Result<TagRecord> {
+        if let Some(parent_id) = parent_id {
+            Self::get_tag_inner(db, parent_id)?;
+        }
+        // 新标签排在最后
+        db.execute("
+            INSERT INTO tags (name, sort_order, parent_id) 
+            VALUES (?1, (SELECT IFNULL(MAX(sort_order), 0) + 1 FROM tags), ?2);
+        ", params![name, parent_id])?;
         
-        let ret = db.prepare("SELECT * FROM tags WHERE id = ?1")?.
-                    query_row(params![db.last_insert_rowid()], TagRecord::parse)?;
+        Self::get_tag_inner(db, db.last_insert_rowid())
+    }
+
+    fn get_tag_inner(db: &Connection, id: i64) -> Result<TagRecord> {
+        let ret = db.prepare(&format!("SELECT {} FROM tags WHERE id = ?1", TagRecord::COLUMNS))?.
+                    query_row(params![id], TagRecord::parse)?;
         Ok(ret)
     }
 
-    /// 删除标签
+    /// 修改标签，为 None 的字段保持不变，color/icon 传空字符串表示清除
+    pub fn update_tag(&self, id: i64, name: Option<String>, color: Option<String>, icon: Option<String>) -> Result<TagRecord> {
+        Self::update_tag_inner(&self.db.lock().unwrap(), id, name, color, icon)
+    }
+
+    fn update_tag_inner(db: &Connection, id: i64, name: Option<String>, color: Option<String>, icon: Option<String>) -> Result<TagRecord> {
+        db.execute("
+            UPDATE tags SET
+                name = IFNULL(?2, name),
+                color = CASE WHEN ?3 IS NULL THEN color ELSE NULLIF(?3, '') END,
+                icon = CASE WHEN ?4 IS NULL THEN icon ELSE NULLIF(?4, '') END
+            WHERE id = ?1;
+        ", params![id, name, color, icon])?;
+        Self::get_tag_inner(db, id)
+    }
+
+    /// 把标签关联到目录，dir 为 None 时取消关联
+    pub fn set_tag_linked_dir(&self, id: i64, dir: Option<String>) -> Result<TagRecord> {
+        if let Some(dir) = &dir {
+            if !Path::new(dir).is_dir() {
+                return Err(StorageError::Invalid(format!("{} is not a directory", dir)));
+            }
+        }
+        let tag = {
+            let db = self.db.lock().unwrap();
+            db.execute("UPDATE tags SET linked_dir = ?2 WHERE id = ?1;", params![id, dir])?;
+            Self::get_tag_inner(&db, id)?
+        };
+        match &dir {
+            Some(dir) => self.linked.link(id, Path::new(dir)),
+            None => self.linked.unlink(id),
+        }
+        Ok(tag)
+    }
+
+    /// 按 ids 的顺序重新设置标签排序，未出现的标签排在后面
+    pub fn reorder_tags(&self, ids: &[i64]) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        tx.execute("UPDATE tags SET sort_order = sort_order + ?1;", params![ids.len() as i64])?;
+        for (i, id) in ids.iter().enumerate() {
+            tx.execute("UPDATE tags SET sort_order = ?1 WHERE id = ?2;", params![i as i64, id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 移动标签到另一个标签下，parent_id 为 None 时移动到顶层
+    pub fn move_tag(&self, id: i64, parent_id: Option<i64>) -> Result<TagRecord> {
+        let db = self.db.lock().unwrap();
+        Self::get_tag_inner(&db, id)?;
+        if let Some(parent_id) = parent_id {
+            Self::get_tag_inner(&db, parent_id)?;
+            // 新的父标签不能是自己或自己的子孙，否则会成环
+            let is_descendant: bool = db.query_row(
+                &format!("{} SELECT EXISTS(SELECT 1 FROM sub_tags WHERE id = ?2);", SUB_TAGS_CTE),
+                params![id, parent_id],
+                |row| row.get(0),
+            )?;
+            if is_descendant {
+                return Err(StorageError::Invalid(format!("tag {} can not be moved into its descendant {}", id, parent_id)));
+            }
+        }
+        db.execute("UPDATE tags SET parent_id = ?2 WHERE id = ?1;", params![id, parent_id])?;
+        Self::get_tag_inner(&db, id)
+    }
+
+    /// 删除标签，子标签会被挂到该标签的父标签下
     pub fn delete_tag(&self, id: i64) -> Result<()> {
         let mut db = self.db.lock().unwrap();
-        // 所属该标签下的记录也要删除
         let tx = db.transaction()?;
-        tx.execute("DELETE FROM tags WHERE id = ?1;", params![id])?;
-        tx.execute("DELETE FROM clipboard WHERE id IN (SELECT clipboard_id FROM clipboard_tags WHERE tag_id = ?1);", params![id])?;
-        tx.execute("DELETE FROM clipboard_tags WHERE tag_id = ?1;", params![id])?;
+        Self::delete_tag_inner(&tx, id)?;
         tx.commit()?;
+        self.linked.unlink(id);
         Ok(())
     }
 
-    /// 获取某个标签下的所有记录
+    fn delete_tag_inner(db: &Connection, id: i64) -> Result<()> {
+        db.execute("
+            UPDATE tags SET parent_id = (SELECT parent_id FROM tags WHERE id = ?1) WHERE parent_id = ?1;
+        ", params![id])?;
+        db.execute("DELETE FROM tags WHERE id = ?1;", params![id])?;
+        // 所属该标签下的记录也要删除
+        db.execute("DELETE FROM clipboard WHERE id IN (SELECT clipboard_id FROM clipboard_tags WHERE tag_id = ?1);", params![id])?;
+        db.execute("DELETE FROM clipboard_tags WHERE tag_id = ?1;", params![id])?;
+        Ok(())
+    }
+
+    /// 剪贴板历史标签的名称是 defaults 中的某个默认名称时改为 name，用户改过的名称不变
+    pub fn rename_history_tag(&self, defaults: &[String], name: &str) -> Result<()> {
+        let defaults = Rc::new(defaults.iter().cloned().map(Value::from).collect::<Vec<Value>>());
+        self.db.lock().unwrap().execute(
+            "UPDATE tags SET name = ?1 WHERE id = 0 AND name IN rarray(?2);", params![name, defaults])?;
+        Ok(())
+    }
+
+    /// 获取某个标签下的所有记录，关联目录中的记录排在本地记录之后
     pub fn get_records_with_tag(&self, tag_id: i64) -> Result<Vec<ClipboardRecord>> {
-        let db = self.db.lock().unwrap();
+        let mut ret = self.get_local_records_with_tag(tag_id)?;
+        ret.extend(self.get_linked_records(&[tag_id])?);
+        Ok(ret)
+    }
+
+    /// 获取某个标签下保存在数据库中的记录，不含关联目录中的记录
+    pub fn get_local_records_with_tag(&self, tag_id: i64) -> Result<Vec<ClipboardRecord>> {
+        Self::get_local_records_with_tag_inner(&self.db.lock().unwrap(), tag_id)
+    }
+
+    fn get_local_records_with_tag_inner(db: &Connection, tag_id: i64) -> Result<Vec<ClipboardRecord>> {
         let mut stmt = db.prepare("
             SELECT c.* FROM clipboard c
             INNER JOIN clipboard_tags ct 
-            ON ct.tag_id = ?1 AND ct.clipboard_id = c.id;"
+            ON ct.tag_id = ?1 AND ct.clipboard_id = c.id
+            ORDER BY ct.position, ct.id;"
         )?;
         let rows = stmt.query_map(params![tag_id], ClipboardRecord::parse)?;
         let mut ret = Vec::new();
         for r in rows {
-            ret.push(r.unwrap());
+            ret.push(r?);
+        }
+        Ok(ret)
+    }
+
+    /// 获取剪贴板历史中最近的 n 条记录，按从新到旧排列
+    pub fn latest_records(&self, n: usize) -> Result<Vec<ClipboardRecord>> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("
+            SELECT c.* FROM clipboard_tags ct
+            INNER JOIN clipboard c ON c.id = ct.clipboard_id
+            WHERE ct.tag_id = 0
+            ORDER BY ct.position DESC, ct.id DESC
+            LIMIT ?1;"
+        )?;
+        let rows = stmt.query_map(params![n as i64], ClipboardRecord::parse)?;
+        let mut ret = Vec::new();
+        for r in rows {
+            ret.push(r?);
         }
         Ok(ret)
     }
 
-    /// 获取一条记录
+    /// 获取某个标签及其所有子孙标签下的记录
+    pub fn get_records_with_tag_recursive(&self, tag_id: i64) -> Result<Vec<ClipboardRecord>> {
+        let mut ret = {
+            let db = self.db.lock().unwrap();
+            let mut stmt = db.prepare(&format!("
+                {} SELECT c.* FROM clipboard c
+                INNER JOIN clipboard_tags ct
+                ON ct.tag_id IN (SELECT id FROM sub_tags) AND ct.clipboard_id = c.id
+                ORDER BY ct.position, ct.id;
+            ", SUB_TAGS_CTE))?;
+            let rows = stmt.query_map(params![tag_id], ClipboardRecord::parse)?;
+            let mut ret = Vec::new();
+            for r in rows {
+                ret.push(r?);
+            }
+            ret
+        };
+        let mut tag_ids = vec![tag_id];
+        tag_ids.extend(self.get_descendant_tags(tag_id)?.iter().map(|tag| tag.id));
+        ret.extend(self.get_linked_records(&tag_ids)?);
+        Ok(ret)
+    }
+
+    /// 获取标签下关联目录中的记录，同一个文件只返回一次
+    fn get_linked_records(&self, tag_ids: &[i64]) -> Result<Vec<ClipboardRecord>> {
+        let mut ret: Vec<ClipboardRecord> = Vec::new();
+        for tag_id in tag_ids {
+            let name: Option<String> = self.db.lock().unwrap()
+                .query_row("SELECT IFNULL(name, '') FROM tags WHERE id = ?1;", params![tag_id], |row| row.get(0))
+                .optional()?;
+            if let Some(name) = name {
+                for record in self.linked.records(*tag_id, &name) {
+                    if !ret.iter().any(|r| r.id == record.id) {
+                        ret.push(record);
+                    }
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// 按文本搜索记录，tag_id 为 None 时搜索所有标签，按时间从新到旧
+    pub fn search_records(&self, query: &str, tag_id: Option<i64>, limit: usize) -> Result<Vec<ClipboardRecord>> {
+        // data 中保存的是 JSON，关键字也按 JSON 字符串转义后再匹配
+        let escaped = serde_json::to_string(query)?;
+        let escaped = &escaped[1..escaped.len() - 1];
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("
+            SELECT * FROM clipboard c
+            WHERE c.content_type != 'image' AND instr(lower(c.data), lower(?1)) > 0
+            AND (?2 IS NULL OR EXISTS(SELECT 1 FROM clipboard_tags ct WHERE ct.clipboard_id = c.id AND ct.tag_id = ?2))
+            ORDER BY c.create_at DESC, c.id DESC
+            LIMIT ?3;
+        ")?;
+        let rows = stmt.query_map(params![escaped, tag_id, limit as i64], ClipboardRecord::parse)?;
+        let mut ret = Vec::new();
+        for r in rows {
+            ret.push(r?);
+        }
+        Ok(ret)
+    }
+
+    /// 获取一条记录，id 为负数时是关联目录中的记录
     pub fn get_record(&self, id: i64) -> Result<ClipboardRecord> {
+        if LinkedLibrary::is_linked_id(id) {
+            return self.linked.get(id).ok_or(StorageError::Sqlite(rusqlite::Error::QueryReturnedNoRows));
+        }
         let db = self.db.lock().unwrap();
         let ret = db.prepare("SELECT * FROM clipboard WHERE id = ?1")?.
                     query_row(params![id], ClipboardRecord::parse)?;
@@ -291,19 +988,34 @@ impl StorageConn {
 
     /// 在某个标签下插入一条新纪录
     pub fn insert_record_with_tag(&self, record: ClipboardRecord, tag_id: i64) -> Result<ClipboardRecord> {
-        let last_insert_id = {
-            let mut db = self.db.lock().unwrap();
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        let ret = Self::insert_record_with_tag_inner(&tx, record, tag_id)?;
+        tx.commit()?;
+        Ok(ret)
+    }
 
-            let tx = db.transaction()?;
-            tx.execute("INSERT INTO clipboard (main_data, data, content_type, create_at) VALUES (?1, ?2, ?3, ?4);", 
-                params![record.main_data, record.data, record.content_type, record.time])?;
-            tx.execute("INSERT INTO clipboard_tags (clipboard_id, tag_id) VALUES (?1, ?2);", 
-                params![tx.last_insert_rowid(), tag_id])?;
-            tx.commit()?;
+    fn insert_record_with_tag_inner(db: &Connection, record: ClipboardRecord, tag_id: i64) -> Result<ClipboardRecord> {
+        db.execute("INSERT INTO clipboard (main_data, data, content_type, create_at, secret) VALUES (?1, ?2, ?3, ?4, ?5);", 
+            params![record.main_data, record.data, record.content_type, record.time, record.secret])?;
+        let record_id = db.last_insert_rowid();
+        // 新记录排在标签的最后
+        db.execute("
+            INSERT INTO clipboard_tags (clipboard_id, tag_id, position) 
+            VALUES (?1, ?2, (SELECT IFNULL(MAX(position), 0) + ?3 FROM clipboard_tags WHERE tag_id = ?2));
+        ", params![record_id, tag_id, POSITION_GAP])?;
+        let ret = db.prepare("SELECT * FROM clipboard WHERE id = ?1")?.
+                    query_row(params![record_id], ClipboardRecord::parse)?;
+        Ok(ret)
+    }
 
-            db.last_insert_rowid()
-        };
-        self.get_record(last_insert_id)
+    /// 在一个事务中执行 f，f 返回错误时回滚所有修改
+    pub fn transaction<T, E: From<StorageError>>(&self, f: impl FnOnce(&StorageTx) -> result::Result<T, E>) -> result::Result<T, E> {
+        let mut db = self.db.lock().unwrap();
+        let tx = StorageTx(db.transaction().map_err(StorageError::from)?);
+        let ret = f(&tx)?;
+        tx.0.commit().map_err(StorageError::from)?;
+        Ok(ret)
     }
 
     /// 复制一条记录到另一个标签下
@@ -312,8 +1024,83 @@ impl StorageConn {
         Ok(())
     }
 
+    /// 把标签内的一条记录移动到 new_index 处
+    ///
+    /// new_index 是界面中的下标，界面把 get_records_with_tag 的结果倒过来显示，最新的在前，0 表示移到最前
+    pub fn move_record(&self, tag_id: i64, record_id: i64, new_index: usize) -> Result<()> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        let row_id: i64 = tx.query_row(
+            "SELECT id FROM clipboard_tags WHERE tag_id = ?1 AND clipboard_id = ?2;",
+            params![tag_id, record_id],
+            |row| row.get(0),
+        )?;
+
+        // 标签内除了被移动的记录以外的其他记录 (clipboard_tags.id, position)
+        let mut others = {
+            let mut stmt = tx.prepare("
+                SELECT id, position FROM clipboard_tags WHERE tag_id = ?1 AND id != ?2
+                ORDER BY position, id;
+            ")?;
+            let rows = stmt.query_map(params![tag_id, row_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)))?;
+            let mut others = Vec::new();
+            for r in rows {
+                others.push(r?);
+            }
+            others
+        };
+        // 换算成 others 中按 position 升序的下标
+        let index = others.len() - new_index.min(others.len());
+
+        let position = match Self::position_between(&others, index) {
+            Some(position) => position,
+            None => {
+                // 间隔已经小到无法再取中间值，重新按默认间隔编号
+                for (i, (id, position)) in others.iter_mut().enumerate() {
+                    *position = (i + 1) as f64 * POSITION_GAP;
+                    tx.execute("UPDATE clipboard_tags SET position = ?1 WHERE id = ?2;", params![*position, *id])?;
+                }
+                Self::position_between(&others, index).unwrap_or(POSITION_GAP)
+            }
+        };
+        tx.execute("UPDATE clipboard_tags SET position = ?1 WHERE id = ?2;", params![position, row_id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 计算插入到 others[index] 之前的 position，前后已经没有间隔时返回 None
+    fn position_between(others: &[(i64, f64)], index: usize) -> Option<f64> {
+        let prev = index.checked_sub(1).map(|i| others[i].1);
+        let next = others.get(index).map(|(_, position)| *position);
+        let position = match (prev, next) {
+            (None, None) => POSITION_GAP,
+            (Some(prev), None) => prev + POSITION_GAP,
+            (None, Some(next)) => next - POSITION_GAP,
+            (Some(prev), Some(next)) => prev + (next - prev) / 2.0,
+        };
+        if prev.is_some_and(|prev| position <= prev) || next.is_some_and(|next| position >= next) {
+            return None;
+        }
+        Some(position)
+    }
+
+    /// 标记或取消标记记录为敏感内容
+    pub fn set_record_secret(&self, id: i64, secret: bool) -> Result<()> {
+        if LinkedLibrary::is_linked_id(id) {
+            return Err(StorageError::LinkedReadOnly);
+        }
+        let db = self.db.lock().unwrap();
+        match db.execute("UPDATE clipboard SET secret = ?1 WHERE id = ?2;", params![secret, id])? {
+            0 => Err(StorageError::Sqlite(rusqlite::Error::QueryReturnedNoRows)),
+            _ => Ok(()),
+        }
+    }
+
     /// 删除一条记录
     pub fn delete_record(&self, id: i64) -> Result<()> {
+        if LinkedLibrary::is_linked_id(id) {
+            return Err(StorageError::LinkedReadOnly);
+        }
         let mut db = self.db.lock().unwrap();
 
         let tx = db.transaction()?;
@@ -323,4 +1110,390 @@ impl StorageConn {
 
         Ok(())
     }
+
+    /// 本机的同步节点 id
+    pub fn sync_node(&self) -> Result<String> {
+        let db = self.db.lock().unwrap();
+        Ok(db.query_row("SELECT node FROM sync_state;", [], |row| row.get(0))?)
+    }
+
+    /// 已经从某个节点同步到的 seq
+    pub fn sync_peer_seq(&self, node: &str) -> Result<i64> {
+        let db = self.db.lock().unwrap();
+        let seq = db.query_row("SELECT last_seq FROM sync_peers WHERE node = ?1;", params![node], |row| row.get(0))
+            .optional()?;
+        Ok(seq.unwrap_or(0))
+    }
+
+    pub fn set_sync_peer_seq(&self, node: &str, seq: i64) -> Result<()> {
+        let db = self.db.lock().unwrap();
+        db.execute("INSERT OR REPLACE INTO sync_peers (node, last_seq) VALUES (?1, ?2);", params![node, seq])?;
+        Ok(())
+    }
+
+    /// 获取本地 seq 大于 since 的所有变更，以及其中最大的 seq
+    pub fn sync_changes_since(&self, since: i64) -> Result<(Vec<SyncChange>, i64)> {
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare("
+            SELECT entity, uid, clock, node, seq, deleted FROM sync_log WHERE seq > ?1 ORDER BY seq;
+        ")?;
+        let rows = stmt.query_map(params![since], |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, i64>(4)?,
+            row.get::<_, bool>(5)?,
+        )))?;
+        let mut changes = Vec::new();
+        let mut max_seq = since;
+        for r in rows {
+            let (entity, uid, clock, node, seq, deleted) = r?;
+            max_seq = max_seq.max(seq);
+            let entity = match entity.as_str() {
+                "tag" => SyncEntity::Tag,
+                "record" => SyncEntity::Record,
+                _ => continue,
+            };
+            let mut change = SyncChange { entity, uid, clock, node, deleted, tag: None, record: None };
+            if !deleted {
+                match entity {
+                    SyncEntity::Tag => change.tag = db.query_row("
+                        SELECT t.name, t.color, t.icon, p.uid, t.sort_order FROM tags t
+                        LEFT JOIN tags p ON p.id = t.parent_id WHERE t.uid = ?1;
+                    ", params![change.uid], |row| Ok(SyncTag {
+                        name: row.get(0)?,
+                        color: row.get(1)?,
+                        icon: row.get(2)?,
+                        parent_uid: row.get(3)?,
+                        sort_order: row.get(4)?,
+                    })).optional()?,
+                    SyncEntity::Record => change.record = db.query_row("
+                        SELECT t.uid, ct.position, c.main_data, c.data, c.content_type, c.create_at, c.secret FROM clipboard c
+                        INNER JOIN clipboard_tags ct ON ct.clipboard_id = c.id AND ct.tag_id != 0
+                        INNER JOIN tags t ON t.id = ct.tag_id WHERE c.uid = ?1;
+                    ", params![change.uid], |row| Ok(SyncRecord {
+                        tag_uid: row.get(0)?,
+                        position: row.get(1)?,
+                        main_data: row.get(2)?,
+                        data: row.get(3)?,
+                        content_type: row.get(4)?,
+                        time: row.get(5)?,
+                        secret: row.get(6)?,
+                    })).optional()?,
+                }
+                // 实体已经不存在，等它的删除变更
+                if change.tag.is_none() && change.record.is_none() {
+                    continue;
+                }
+            }
+            changes.push(change);
+        }
+        Ok((changes, max_seq))
+    }
+
+    /// 应用来自其他节点的变更，只应用比本地更新的，返回实际应用的条数
+    pub fn apply_sync_changes(&self, changes: &[SyncChange]) -> Result<usize> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        // 应用远程变更时不触发 sync_log 触发器
+        tx.execute("UPDATE sync_state SET applying = 1;", [])?;
+
+        let mut accepted = Vec::new();
+        for change in changes {
+            let local: Option<(i64, String)> = tx.query_row(
+                "SELECT clock, node FROM sync_log WHERE entity = ?1 AND uid = ?2;",
+                params![change.entity.as_str(), change.uid],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).optional()?;
+            if local.map_or(true, |(clock, node)| change.is_newer_than(clock, &node)) {
+                accepted.push(change);
+            }
+        }
+
+        // 先更新标签，再设置父标签（父标签可能排在后面），然后是记录，最后删除标签
+        let mut applied = Vec::new();
+        for change in accepted.iter().filter(|c| c.entity == SyncEntity::Tag && !c.deleted) {
+            if let Some(tag) = &change.tag {
+                Self::apply_sync_tag(&tx, &change.uid, tag)?;
+                applied.push(*change);
+            }
+        }
+        for change in accepted.iter().filter(|c| c.entity == SyncEntity::Tag && !c.deleted) {
+            if let Some(tag) = &change.tag {
+                Self::apply_sync_tag_parent(&tx, &change.uid, tag)?;
+            }
+        }
+        for change in accepted.iter().filter(|c| c.entity == SyncEntity::Record) {
+            if change.deleted {
+                tx.execute("DELETE FROM clipboard_tags WHERE clipboard_id IN (SELECT id FROM clipboard WHERE uid = ?1);", params![change.uid])?;
+                tx.execute("DELETE FROM clipboard WHERE uid = ?1;", params![change.uid])?;
+                applied.push(*change);
+            } else if let Some(record) = &change.record {
+                if Self::apply_sync_record(&tx, &change.uid, record)? {
+                    applied.push(*change);
+                }
+            }
+        }
+        for change in accepted.iter().filter(|c| c.entity == SyncEntity::Tag && c.deleted) {
+            let id: Option<i64> = tx.query_row("SELECT id FROM tags WHERE uid = ?1;", params![change.uid], |row| row.get(0))
+                .optional()?;
+            if let Some(id) = id.filter(|id| *id != 0) {
+                Self::delete_tag_inner(&tx, id)?;
+            }
+            applied.push(*change);
+        }
+
+        // 记录到本地 sync_log，并推进本地时钟
+        for change in &applied {
+            tx.execute("UPDATE sync_state SET clock = MAX(clock, ?1), seq = seq + 1;", params![change.clock])?;
+            tx.execute("
+                INSERT OR REPLACE INTO sync_log (entity, uid, clock, node, seq, deleted)
+                SELECT ?1, ?2, ?3, ?4, seq, ?5 FROM sync_state;
+            ", params![change.entity.as_str(), change.uid, change.clock, change.node, change.deleted])?;
+        }
+        tx.execute("UPDATE sync_state SET applying = 0;", [])?;
+        tx.commit()?;
+        Ok(applied.len())
+    }
+
+    fn apply_sync_tag(db: &Connection, uid: &str, tag: &SyncTag) -> Result<()> {
+        let id: Option<i64> = db.query_row("SELECT id FROM tags WHERE uid = ?1;", params![uid], |row| row.get(0))
+            .optional()?;
+        match id {
+            Some(id) => db.execute("
+                UPDATE tags SET name = ?2, color = ?3, icon = ?4, sort_order = ?5 WHERE id = ?1;
+            ", params![id, tag.name, tag.color, tag.icon, tag.sort_order])?,
+            None => db.execute("
+                INSERT INTO tags (name, color, icon, sort_order, uid) VALUES (?1, ?2, ?3, ?4, ?5);
+            ", params![tag.name, tag.color, tag.icon, tag.sort_order, uid])?,
+        };
+        Ok(())
+    }
+
+    fn apply_sync_tag_parent(db: &Connection, uid: &str, tag: &SyncTag) -> Result<()> {
+        let id: i64 = db.query_row("SELECT id FROM tags WHERE uid = ?1;", params![uid], |row| row.get(0))?;
+        let parent_id: Option<i64> = match &tag.parent_uid {
+            Some(parent_uid) => db.query_row("SELECT id FROM tags WHERE uid = ?1;", params![parent_uid], |row| row.get(0))
+                .optional()?,
+            None => None,
+        };
+        if let Some(parent_id) = parent_id {
+            // 两端并发移动可能成环，这种情况保留本地的父标签
+            let is_descendant: bool = db.query_row(
+                &format!("{} SELECT EXISTS(SELECT 1 FROM sub_tags WHERE id = ?2);", SUB_TAGS_CTE),
+                params![id, parent_id],
+                |row| row.get(0),
+            )?;
+            if is_descendant {
+                return Ok(());
+            }
+        }
+        db.execute("UPDATE tags SET parent_id = ?2 WHERE id = ?1;", params![id, parent_id])?;
+        Ok(())
+    }
+
+    /// 所属标签在本地不存在时跳过，返回是否应用
+    fn apply_sync_record(db: &Connection, uid: &str, record: &SyncRecord) -> Result<bool> {
+        let tag_id: Option<i64> = db.query_row("SELECT id FROM tags WHERE uid = ?1 AND id != 0;", params![record.tag_uid], |row| row.get(0))
+            .optional()?;
+        let tag_id = match tag_id {
+            Some(tag_id) => tag_id,
+            None => return Ok(false),
+        };
+        let id: Option<i64> = db.query_row("SELECT id FROM clipboard WHERE uid = ?1;", params![uid], |row| row.get(0))
+            .optional()?;
+        match id {
+            Some(id) => {
+                db.execute("
+                    UPDATE clipboard_tags SET tag_id = ?2, position = ?3 WHERE clipboard_id = ?1 AND tag_id != 0;
+                ", params![id, tag_id, record.position])?;
+                db.execute("UPDATE clipboard SET secret = ?2 WHERE id = ?1;", params![id, record.secret])?;
+            }
+            None => {
+                db.execute("
+                    INSERT INTO clipboard (main_data, data, content_type, create_at, uid, secret) VALUES (?1, ?2, ?3, ?4, ?5, ?6);
+                ", params![record.main_data, record.data, record.content_type, record.time, uid, record.secret])?;
+                db.execute("
+                    INSERT INTO clipboard_tags (clipboard_id, tag_id, position) VALUES (?1, ?2, ?3);
+                ", params![db.last_insert_rowid(), tag_id, record.position])?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// StorageConn::transaction 中的事务，提供导入等批量操作需要的方法
+pub struct StorageTx<'a>(Transaction<'a>);
+
+impl StorageTx<'_> {
+    pub fn get_tags(&self) -> Result<Vec<TagRecord>> {
+        StorageConn::get_tags_inner(&self.0)
+    }
+
+    /// 创建一个标签并设置颜色和图标
+    pub fn insert_tag(&self, name: String, parent_id: Option<i64>, color: Option<String>, icon: Option<String>) -> Result<TagRecord> {
+        let tag = StorageConn::insert_tag_inner(&self.0, name, parent_id)?;
+        StorageConn::update_tag_inner(&self.0, tag.id, None, color, icon)
+    }
+
+    /// 所有标签下的记录，包括剪贴板历史，不含关联目录中的记录
+    pub fn get_all_local_records(&self) -> Result<Vec<(i64, ClipboardRecord)>> {
+        let mut stmt = self.0.prepare("
+            SELECT c.*, ct.tag_id FROM clipboard c
+            INNER JOIN clipboard_tags ct ON ct.clipboard_id = c.id
+            ORDER BY ct.tag_id, ct.position, ct.id;"
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get("tag_id")?, ClipboardRecord::parse(row)?)))?;
+        let mut ret = Vec::new();
+        for r in rows {
+            ret.push(r?);
+        }
+        Ok(ret)
+    }
+
+    pub fn insert_record_with_tag(&self, record: ClipboardRecord, tag_id: i64) -> Result<ClipboardRecord> {
+        StorageConn::insert_record_with_tag_inner(&self.0, record, tag_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_record(text: &str) -> ClipboardRecord {
+        let text = ClipboardFormatContent::Text(text.to_string());
+        ClipboardContent { main_format: text.clone(), data: vec![text] }.try_into().unwrap()
+    }
+
+    fn record_texts(db: &StorageConn, tag_id: i64) -> Vec<String> {
+        db.get_records_with_tag(tag_id).unwrap().into_iter().map(|record| record.main_data).collect()
+    }
+
+    #[test]
+    fn tag_crud() {
+        let db = StorageConn::open_in_memory().unwrap();
+        let work = db.insert_tag("work".to_string(), None).unwrap();
+        let child = db.insert_tag("child".to_string(), Some(work.id)).unwrap();
+        assert_eq!(child.parent_id, Some(work.id));
+        assert!(child.sort_order > work.sort_order);
+        assert!(db.insert_tag("orphan".to_string(), Some(404)).is_err());
+
+        let work = db.update_tag(work.id, None, Some("#ff0000".to_string()), Some("star".to_string())).unwrap();
+        assert_eq!((work.name.as_str(), work.color.as_deref(), work.icon.as_deref()), ("work", Some("#ff0000"), Some("star")));
+        // 空字符串清除，None 保持不变
+        let work = db.update_tag(work.id, Some("job".to_string()), Some(String::new()), None).unwrap();
+        assert_eq!((work.name.as_str(), work.color, work.icon.as_deref()), ("job", None, Some("star")));
+
+        db.insert_record_with_tag(text_record("a"), work.id).unwrap();
+        db.delete_tag(work.id).unwrap();
+        let tags = db.get_tags().unwrap();
+        assert!(tags.iter().all(|tag| tag.id != work.id));
+        // 子标签挂到被删除标签的父标签下，标签下的记录一起删除
+        assert_eq!(tags.iter().find(|tag| tag.id == child.id).unwrap().parent_id, None);
+        assert_eq!(db.stats().unwrap().records, 0);
+    }
+
+    #[test]
+    fn move_tag_rejects_cycles() {
+        let db = StorageConn::open_in_memory().unwrap();
+        let a = db.insert_tag("a".to_string(), None).unwrap();
+        let b = db.insert_tag("b".to_string(), Some(a.id)).unwrap();
+        let c = db.insert_tag("c".to_string(), Some(b.id)).unwrap();
+
+        assert!(matches!(db.move_tag(a.id, Some(a.id)), Err(StorageError::Invalid(_))));
+        assert!(matches!(db.move_tag(a.id, Some(c.id)), Err(StorageError::Invalid(_))));
+        assert!(db.move_tag(a.id, Some(404)).is_err());
+        assert_eq!(db.get_descendant_tags(a.id).unwrap().len(), 2);
+
+        assert_eq!(db.move_tag(c.id, None).unwrap().parent_id, None);
+        assert_eq!(db.move_tag(a.id, Some(c.id)).unwrap().parent_id, Some(c.id));
+        let ids: Vec<i64> = db.get_descendant_tags(c.id).unwrap().iter().map(|tag| tag.id).collect();
+        assert_eq!(ids, vec![a.id, b.id]);
+    }
+
+    #[test]
+    fn position_between_leaves_gaps() {
+        let others = [(1, 1024.0), (2, 2048.0)];
+        assert_eq!(StorageConn::position_between(&[], 0), Some(POSITION_GAP));
+        assert_eq!(StorageConn::position_between(&others, 0), Some(0.0));
+        assert_eq!(StorageConn::position_between(&others, 1), Some(1536.0));
+        assert_eq!(StorageConn::position_between(&others, 2), Some(2048.0 + POSITION_GAP));
+        // 前后两条之间已经没有可用的值
+        assert_eq!(StorageConn::position_between(&[(1, 1.0), (2, 1.0)], 1), None);
+        let next = f64::from_bits(1024f64.to_bits() + 1);
+        assert_eq!(StorageConn::position_between(&[(1, 1024.0), (2, next)], 1), None);
+    }
+
+    #[test]
+    fn move_record_renumbers_exhausted_positions() {
+        let db = StorageConn::open_in_memory().unwrap();
+        let tag = db.insert_tag("tag".to_string(), None).unwrap();
+        let ids: Vec<i64> = ["a", "b", "c"].iter()
+            .map(|text| db.insert_record_with_tag(text_record(text), tag.id).unwrap().id)
+            .collect();
+        let texts: Vec<String> = ["a", "b", "c"].iter().map(|text| text_record(text).main_data).collect();
+        // 界面中的顺序
+        let displayed = |db: &StorageConn| record_texts(db, tag.id).into_iter().rev().collect::<Vec<_>>();
+        assert_eq!(displayed(&db), vec![texts[2].clone(), texts[1].clone(), texts[0].clone()]);
+
+        db.move_record(tag.id, ids[0], 0).unwrap();
+        assert_eq!(displayed(&db), vec![texts[0].clone(), texts[2].clone(), texts[1].clone()]);
+        db.move_record(tag.id, ids[0], 2).unwrap();
+        assert_eq!(displayed(&db), vec![texts[2].clone(), texts[1].clone(), texts[0].clone()]);
+
+        // 所有记录的 position 相同，无法取中间值时重新编号
+        db.db.lock().unwrap().execute("UPDATE clipboard_tags SET position = 1.0 WHERE tag_id = ?1;", params![tag.id]).unwrap();
+        db.move_record(tag.id, ids[0], 1).unwrap();
+        assert_eq!(displayed(&db), vec![texts[2].clone(), texts[0].clone(), texts[1].clone()]);
+        let positions: Vec<f64> = {
+            let db = db.db.lock().unwrap();
+            let mut stmt = db.prepare("SELECT position FROM clipboard_tags WHERE tag_id = ?1 ORDER BY position;").unwrap();
+            let rows = stmt.query_map(params![tag.id], |row| row.get(0)).unwrap();
+            rows.map(|r| r.unwrap()).collect()
+        };
+        assert_eq!(positions, vec![POSITION_GAP, POSITION_GAP * 1.5, POSITION_GAP * 2.0]);
+    }
+
+    #[test]
+    fn migrations_upgrade_v0_database() {
+        let backup_dir = std::env::temp_dir().join(format!("drawer-migration-test-{}", std::process::id()));
+        let conn = StorageConn {
+            sink: None,
+            db: Arc::new(Mutex::new(Connection::open_in_memory().unwrap())),
+            backup_dir: backup_dir.clone(),
+            gc_config: Arc::new(Mutex::new(GcConfig::default())),
+            gc_close_handle: Mutex::new(mpsc::channel().0),
+            linked: LinkedLibrary::default(),
+        };
+        rusqlite::vtab::array::load_module(&conn.db.lock().unwrap()).unwrap();
+        conn.init_table().unwrap();
+        let record = text_record("old");
+        conn.db.lock().unwrap().execute_batch(&format!("
+            INSERT INTO tags (id, name) VALUES (1, 'work');
+            INSERT INTO clipboard (id, main_data, data, content_type, create_at) VALUES (1, '{}', '{}', 'text', 1);
+            INSERT INTO clipboard_tags (clipboard_id, tag_id) VALUES (1, 1);
+        ", record.main_data, record.data)).unwrap();
+
+        conn.migrate(None).unwrap();
+        let version: i64 = conn.db.lock().unwrap().query_row("PRAGMA user_version;", [], |row| row.get(0)).unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+        // 有数据的数据库迁移前先备份
+        let backups = conn.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].name.contains("migration-v0"));
+
+        let tag = conn.get_tags().unwrap().into_iter().find(|tag| tag.id == 1).unwrap();
+        assert_eq!((tag.sort_order, tag.parent_id, tag.linked_dir), (1, None, None));
+        let records = conn.get_records_with_tag(1).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(!records[0].secret);
+        let (changes, _) = conn.sync_changes_since(0).unwrap();
+        assert_eq!(changes.len(), 2);
+
+        // 已经是最新版本时不再备份
+        conn.migrate(None).unwrap();
+        assert_eq!(conn.list_backups().unwrap().len(), 1);
+        fs::remove_dir_all(&backup_dir).unwrap();
+    }
 }
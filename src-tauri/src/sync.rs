@@ -0,0 +1,524 @@
+use std::{fmt, io::{self, BufRead, BufReader, Write}, net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs}, result, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, thread, time::{Duration, Instant}};
+
+use chacha20poly1305::{aead::{Aead, KeyInit}, ChaCha20Poly1305, Nonce};
+use hmac::{Hmac, Mac};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+
+use crate::storage::{StorageConn, StorageError, SyncChange};
+
+/// 默认的同步端口
+pub const SYNC_PORT: u16 = 7879;
+/// mDNS 服务类型
+const SERVICE_TYPE: &str = "_drawer-sync._tcp.local.";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// 握手时每端生成的随机数的字节数
+const NONCE_LEN: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 包装同步过程中的错误
+#[derive(Debug)]
+pub enum SyncError {
+    Io(io::Error),
+    Serde(serde_json::Error),
+    Storage(StorageError),
+    Mdns(mdns_sd::Error),
+    /// 没有设置共享密钥
+    EmptySecret,
+    /// 对端不符合协议，或共享密钥不一致
+    Protocol(String),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Io(err) => write!(f, "io error: {}", err),
+            SyncError::Serde(err) => write!(f, "serde error: {}", err),
+            SyncError::Storage(err) => write!(f, "{}", err),
+            SyncError::Mdns(err) => write!(f, "mdns error: {}", err),
+            SyncError::EmptySecret => write!(f, "sync secret is not set"),
+            SyncError::Protocol(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<io::Error> for SyncError {
+    fn from(value: io::Error) -> Self {
+        SyncError::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for SyncError {
+    fn from(value: serde_json::Error) -> Self {
+        SyncError::Serde(value)
+    }
+}
+
+impl From<StorageError> for SyncError {
+    fn from(value: StorageError) -> Self {
+        SyncError::Storage(value)
+    }
+}
+
+impl From<mdns_sd::Error> for SyncError {
+    fn from(value: mdns_sd::Error) -> Self {
+        SyncError::Mdns(value)
+    }
+}
+
+pub type Result<T> = result::Result<T, SyncError>;
+
+/// 同步协议消息，每条消息是一行 JSON，握手之后的消息加密后以 base64 发送
+///
+/// 客户端：Hello → 收 Hello → Auth → 收 Auth → Pull → 收 Changes → 收 Pull → Changes
+/// 服务端：收 Hello → Hello → 收 Auth → Auth → 收 Pull → Changes → Pull → 收 Changes
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+enum Message {
+    /// nonce 为 base64 编码的随机数
+    Hello { node: String, nonce: String },
+    /// 用共享密钥对双方随机数计算的 HMAC，证明自己知道密钥
+    Auth { proof: String },
+    Pull { since: i64 },
+    Changes { changes: Vec<SyncChange>, seq: i64 },
+}
+
+/// 一次同步的结果
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct SyncReport {
+    pub peer: String,
+    pub sent: usize,
+    pub received: usize,
+    pub applied: usize,
+}
+
+/// 局域网内发现的其他 Drawer
+#[derive(Serialize, Clone, Debug)]
+pub struct SyncPeer {
+    pub node: String,
+    pub addr: String,
+}
+
+/// 握手后的加密通道，密钥由共享密钥和双方的随机数派生，每次连接都不同。
+/// 两个方向各用一个递增的计数作为 nonce，首字节区分方向
+struct Cipher {
+    aead: ChaCha20Poly1305,
+    is_client: bool,
+    sent: u64,
+    received: u64,
+}
+
+impl Cipher {
+    fn nonce(from_client: bool, counter: u64) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[0] = from_client as u8;
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        nonce.into()
+    }
+
+    fn encrypt(&mut self, plain: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Self::nonce(self.is_client, self.sent);
+        self.sent += 1;
+        self.aead.encrypt(&nonce, plain).map_err(|_| SyncError::Protocol("failed to encrypt message".to_string()))
+    }
+
+    fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Self::nonce(!self.is_client, self.received);
+        self.received += 1;
+        self.aead.decrypt(&nonce, data).map_err(|_| SyncError::Protocol("failed to decrypt message".to_string()))
+    }
+}
+
+struct Session {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    cipher: Option<Cipher>,
+}
+
+impl Session {
+    fn new(stream: TcpStream) -> Result<Session> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        Ok(Session {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            cipher: None,
+        })
+    }
+
+    fn send(&mut self, msg: &Message) -> Result<()> {
+        let json = serde_json::to_vec(msg)?;
+        match &mut self.cipher {
+            Some(cipher) => self.writer.write_all(base64::encode(cipher.encrypt(&json)?).as_bytes())?,
+            None => self.writer.write_all(&json)?,
+        }
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<Message> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(SyncError::Protocol("connection closed".to_string()));
+        }
+        match &mut self.cipher {
+            Some(cipher) => {
+                let data = base64::decode(line.trim_end())
+                    .map_err(|err| SyncError::Protocol(format!("invalid message: {}", err)))?;
+                Ok(serde_json::from_slice(&cipher.decrypt(&data)?)?)
+            }
+            None => Ok(serde_json::from_str(&line)?),
+        }
+    }
+
+    /// 交换随机数并互相证明知道共享密钥，之后的消息都加密，返回对端的节点 id。
+    /// 服务端先校验客户端的证明再发出自己的，不知道密钥的客户端拿不到任何可用的 HMAC
+    fn handshake(&mut self, db: &StorageConn, secret: &str, is_client: bool) -> Result<String> {
+        check_secret(secret)?;
+        let node = db.sync_node()?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let hello = Message::Hello { node: node.clone(), nonce: base64::encode(nonce) };
+        if is_client {
+            self.send(&hello)?;
+        }
+        let (peer, peer_nonce) = match self.recv()? {
+            Message::Hello { node: peer, nonce: peer_nonce } => (peer, peer_nonce),
+            msg => return Err(SyncError::Protocol(format!("expect Hello, got {:?}", msg))),
+        };
+        if !is_client {
+            self.send(&hello)?;
+        }
+
+        // 证明和会话密钥都绑定客户端、服务端各自的随机数和节点 id
+        let own_nonce = base64::encode(nonce);
+        let transcript = if is_client {
+            [own_nonce.as_str(), peer_nonce.as_str(), node.as_str(), peer.as_str()]
+        } else {
+            [peer_nonce.as_str(), own_nonce.as_str(), peer.as_str(), node.as_str()]
+        };
+        let proof = |role: &str| hmac(secret, role, &transcript);
+        let own_role = if is_client { "client" } else { "server" };
+        let peer_role = if is_client { "server" } else { "client" };
+        let own_proof = Message::Auth { proof: base64::encode(proof(own_role).finalize().into_bytes()) };
+        if is_client {
+            self.send(&own_proof)?;
+        }
+        match self.recv()? {
+            Message::Auth { proof: peer_proof } => {
+                let peer_proof = base64::decode(peer_proof).unwrap_or_default();
+                if proof(peer_role).verify_slice(&peer_proof).is_err() {
+                    return Err(SyncError::Protocol("secret mismatch".to_string()));
+                }
+            }
+            msg => return Err(SyncError::Protocol(format!("expect Auth, got {:?}", msg))),
+        }
+        if !is_client {
+            self.send(&own_proof)?;
+        }
+
+        let key = proof("session-key").finalize().into_bytes();
+        self.cipher = Some(Cipher {
+            aead: ChaCha20Poly1305::new(&key),
+            is_client,
+            sent: 0,
+            received: 0,
+        });
+        Ok(peer)
+    }
+
+    /// 向对端拉取变更并应用
+    fn pull(&mut self, db: &StorageConn, peer: &str, report: &mut SyncReport) -> Result<()> {
+        self.send(&Message::Pull { since: db.sync_peer_seq(peer)? })?;
+        match self.recv()? {
+            Message::Changes { changes, seq } => {
+                report.received = changes.len();
+                report.applied = db.apply_sync_changes(&changes)?;
+                db.set_sync_peer_seq(peer, seq)?;
+                Ok(())
+            }
+            msg => Err(SyncError::Protocol(format!("expect Changes, got {:?}", msg))),
+        }
+    }
+
+    /// 响应对端的拉取
+    fn push(&mut self, db: &StorageConn, report: &mut SyncReport) -> Result<()> {
+        match self.recv()? {
+            Message::Pull { since } => {
+                let (changes, seq) = db.sync_changes_since(since)?;
+                report.sent = changes.len();
+                self.send(&Message::Changes { changes, seq })
+            }
+            msg => Err(SyncError::Protocol(format!("expect Pull, got {:?}", msg))),
+        }
+    }
+}
+
+/// 用共享密钥对 role 和握手内容计算 HMAC，各部分之间用 0 分隔
+fn hmac(secret: &str, role: &str, transcript: &[&str]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(b"drawer-sync");
+    for part in std::iter::once(role).chain(transcript.iter().copied()) {
+        mac.update(b"\0");
+        mac.update(part.as_bytes());
+    }
+    mac
+}
+
+/// 共享密钥不能为空
+pub fn check_secret(secret: &str) -> Result<()> {
+    if secret.is_empty() {
+        return Err(SyncError::EmptySecret);
+    }
+    Ok(())
+}
+
+/// 主动连接 addr 进行一次双向同步，addr 不带端口时使用默认端口
+pub fn sync_with(db: &StorageConn, addr: &str, secret: &str) -> Result<SyncReport> {
+    check_secret(secret)?;
+    let addr = with_default_port(addr);
+    let socket_addr = addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| SyncError::Protocol(format!("invalid address {}", addr)))?;
+    let mut session = Session::new(TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT)?)?;
+    let peer = session.handshake(db, secret, true)?;
+    let mut report = SyncReport { peer, ..Default::default() };
+    session.pull(db, &report.peer.clone(), &mut report)?;
+    session.push(db, &mut report)?;
+    Ok(report)
+}
+
+/// addr 没有指定端口时加上默认端口，IPv6 地址需要加上方括号
+fn with_default_port(addr: &str) -> String {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return addr.to_string();
+    }
+    if let Ok(ip) = addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return SocketAddr::new(ip, SYNC_PORT).to_string();
+    }
+    // 主机名，带端口时只有一个冒号
+    if addr.contains(':') { addr.to_string() } else { format!("{}:{}", addr, SYNC_PORT) }
+}
+
+/// 处理一个连入的同步连接
+pub fn serve(db: &StorageConn, stream: TcpStream, secret: &str) -> Result<SyncReport> {
+    let mut session = Session::new(stream)?;
+    let peer = session.handshake(db, secret, false)?;
+    let mut report = SyncReport { peer, ..Default::default() };
+    session.push(db, &mut report)?;
+    session.pull(db, &report.peer.clone(), &mut report)?;
+    Ok(report)
+}
+
+/// 当前运行的同步服务
+#[derive(Default)]
+pub struct SyncState(pub Mutex<Option<SyncServer>>);
+
+/// 同步服务，监听端口并通过 mDNS 广播自己
+pub struct SyncServer {
+    port: u16,
+    closed: Arc<AtomicBool>,
+    mdns: Option<ServiceDaemon>,
+}
+
+impl Drop for SyncServer {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Some(mdns) = self.mdns.take() {
+            let _ = mdns.shutdown();
+        }
+    }
+}
+
+impl SyncServer {
+    /// 在 port 上监听所有网卡，每个连接在新线程中交给 handler 处理。
+    /// 未通过握手的连接读不到任何数据，之后的内容都是加密的
+    pub fn start<F>(node: &str, port: u16, handler: F) -> Result<SyncServer> where F: Fn(TcpStream) + Send + Sync + 'static {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let port = listener.local_addr()?.port();
+        // 非阻塞轮询，以便 drop 时退出线程
+        listener.set_nonblocking(true)?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let _closed = closed.clone();
+        let handler = Arc::new(handler);
+        thread::spawn(move || while !_closed.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let handler = handler.clone();
+                    thread::spawn(move || {
+                        if stream.set_nonblocking(false).is_ok() {
+                            handler(stream);
+                        }
+                    });
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(200)),
+                Err(err) => log::warn!("failed to accept sync connection: {}", err),
+            }
+        });
+
+        let mdns = match Self::advertise(node, port) {
+            Ok(mdns) => Some(mdns),
+            Err(err) => {
+                log::warn!("failed to advertise sync service: {}", err);
+                None
+            }
+        };
+        Ok(SyncServer { port, closed, mdns })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn advertise(node: &str, port: u16) -> Result<ServiceDaemon> {
+        let mdns = ServiceDaemon::new()?;
+        let name = format!("drawer-{}", node);
+        let properties = [("node", node)];
+        let info = ServiceInfo::new(SERVICE_TYPE, &name, &format!("{}.local.", name), "", port, &properties[..])?
+            .enable_addr_auto();
+        mdns.register(info)?;
+        Ok(mdns)
+    }
+}
+
+/// 通过 mDNS 在 timeout 内查找局域网内的其他 Drawer，不包括自己
+pub fn discover(own_node: &str, timeout: Duration) -> Result<Vec<SyncPeer>> {
+    let mdns = ServiceDaemon::new()?;
+    let receiver = mdns.browse(SERVICE_TYPE)?;
+    let deadline = Instant::now() + timeout;
+    let mut peers: Vec<SyncPeer> = Vec::new();
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(left) {
+            Ok(ServiceEvent::ServiceResolved(info)) => {
+                let node = match info.get_property_val_str("node") {
+                    Some(node) if node != own_node => node.to_string(),
+                    _ => continue,
+                };
+                if let Some(ip) = info.get_addresses().iter().next() {
+                    if !peers.iter().any(|peer| peer.node == node) {
+                        peers.push(SyncPeer { node, addr: format!("{}:{}", ip, info.get_port()) });
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    let _ = mdns.shutdown();
+    Ok(peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use crate::{clipboard::{ClipboardContent, ClipboardFormatContent}, storage::ClipboardRecord};
+    use super::*;
+
+    fn text_record(text: &str) -> ClipboardRecord {
+        let format = ClipboardFormatContent::Text(text.to_string());
+        ClipboardContent { main_format: format.clone(), data: vec![format] }.try_into().unwrap()
+    }
+
+    #[test]
+    fn default_port_is_added_only_when_missing() {
+        assert_eq!(with_default_port("192.168.1.2"), format!("192.168.1.2:{}", SYNC_PORT));
+        assert_eq!(with_default_port("192.168.1.2:9000"), "192.168.1.2:9000");
+        assert_eq!(with_default_port("fe80::1"), format!("[fe80::1]:{}", SYNC_PORT));
+        assert_eq!(with_default_port("[fe80::1]"), format!("[fe80::1]:{}", SYNC_PORT));
+        assert_eq!(with_default_port("[fe80::1]:9000"), "[fe80::1]:9000");
+        assert_eq!(with_default_port("drawer.local"), format!("drawer.local:{}", SYNC_PORT));
+        assert_eq!(with_default_port("drawer.local:9000"), "drawer.local:9000");
+    }
+
+    /// a 作为客户端、b 作为服务端通过回环地址同步一次
+    fn sync_pair(a: &StorageConn, b: &StorageConn, client_secret: &str, server_secret: &str) -> (Result<SyncReport>, Result<SyncReport>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::scope(|s| {
+            let server = s.spawn(|| serve(b, listener.accept().unwrap().0, server_secret));
+            let client = sync_with(a, &addr, client_secret);
+            (client, server.join().unwrap())
+        })
+    }
+
+    fn texts(db: &StorageConn, tag_name: &str) -> Vec<String> {
+        let tag = db.get_tags().unwrap().into_iter().find(|tag| tag.name == tag_name).unwrap();
+        db.get_records_with_tag(tag.id).unwrap().into_iter().map(|record| record.main_data).collect()
+    }
+
+    #[test]
+    fn loopback_sync_exchanges_tags_and_records() {
+        let a = StorageConn::open_in_memory().unwrap();
+        let b = StorageConn::open_in_memory().unwrap();
+        let work = a.insert_tag("work".to_string(), None).unwrap();
+        a.insert_record_with_tag(text_record("from a"), work.id).unwrap();
+        let home = b.insert_tag("home".to_string(), None).unwrap();
+        b.insert_record_with_tag(text_record("from b"), home.id).unwrap();
+        // 剪贴板历史不参与同步
+        a.insert_record(text_record("history")).unwrap();
+
+        let (client, server) = sync_pair(&a, &b, "secret", "secret");
+        let (client, server) = (client.unwrap(), server.unwrap());
+        assert_eq!(client.peer, b.sync_node().unwrap());
+        assert_eq!(server.peer, a.sync_node().unwrap());
+        assert_eq!((client.applied, server.applied), (2, 2));
+        for db in [&a, &b] {
+            assert_eq!(texts(db, "work"), vec![r#"{"Text":"from a"}"#]);
+            assert_eq!(texts(db, "home"), vec![r#"{"Text":"from b"}"#]);
+        }
+        assert!(b.get_records_with_tag(0).unwrap().is_empty());
+
+        // 再次同步时收到的只是对端刚应用过的同一批变更，不会重复应用
+        let (client, server) = sync_pair(&a, &b, "secret", "secret");
+        assert_eq!((client.unwrap().applied, server.unwrap().applied), (0, 0));
+    }
+
+    #[test]
+    fn secret_flag_is_synced() {
+        let a = StorageConn::open_in_memory().unwrap();
+        let b = StorageConn::open_in_memory().unwrap();
+        let work = a.insert_tag("work".to_string(), None).unwrap();
+        let mut record = text_record("token");
+        record.secret = true;
+        let record = a.insert_record_with_tag(record, work.id).unwrap();
+        let secret = |db: &StorageConn| {
+            let tag = db.get_tags().unwrap().into_iter().find(|tag| tag.name == "work").unwrap();
+            db.get_records_with_tag(tag.id).unwrap()[0].secret
+        };
+
+        let (client, _) = sync_pair(&b, &a, "secret", "secret");
+        assert_eq!(client.unwrap().applied, 2);
+        assert!(secret(&b));
+
+        // 取消标记也是一次变更
+        a.set_record_secret(record.id, false).unwrap();
+        let (client, _) = sync_pair(&b, &a, "secret", "secret");
+        assert_eq!(client.unwrap().applied, 1);
+        assert!(!secret(&b));
+    }
+
+    #[test]
+    fn mismatched_secret_is_rejected() {
+        let a = StorageConn::open_in_memory().unwrap();
+        let b = StorageConn::open_in_memory().unwrap();
+        b.insert_tag("home".to_string(), None).unwrap();
+
+        let (client, server) = sync_pair(&a, &b, "secret", "other");
+        assert!(matches!(client, Err(SyncError::Protocol(_))));
+        assert!(matches!(server, Err(SyncError::Protocol(_))));
+        assert_eq!(a.get_tags().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn empty_secret_is_rejected() {
+        let a = StorageConn::open_in_memory().unwrap();
+        assert!(matches!(sync_with(&a, "127.0.0.1:1", ""), Err(SyncError::EmptySecret)));
+    }
+}
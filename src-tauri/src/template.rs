@@ -0,0 +1,194 @@
+use std::{collections::HashMap, fmt::{self, Write}, result};
+
+use chrono::Local;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// 片段记录的 content_type
+pub const SNIPPET_TYPE: &str = "snippet";
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// 展开模板时的错误
+#[derive(Debug)]
+pub enum TemplateError {
+    /// 日期格式不合法
+    InvalidDateFormat(String),
+    /// 缺少 {{input:Name}} 的输入
+    MissingInput(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+pub type Result<T> = result::Result<T, TemplateError>;
+
+/// 模板中的一段
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    /// {{date}} 或 {{date:%Y-%m-%d}}
+    Date(String),
+    /// {{clipboard}} 当前剪贴板中的文本
+    Clipboard,
+    /// {{uuid}}
+    Uuid,
+    /// {{input:Name}} 粘贴前由用户输入
+    Input(String),
+    /// {{cursor}} 粘贴后光标停留的位置
+    Cursor,
+}
+
+/// 片段模板，不认识的 {{...}} 原样保留
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+/// 展开后的文本
+#[derive(Clone, Debug)]
+pub struct Expanded {
+    pub text: String,
+    /// 有 {{cursor}} 时，光标位置之后的字素簇数（\r\n 算一个），粘贴后光标需要左移这么多
+    pub cursor_offset: Option<usize>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Template {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            let end = match rest[start + 2..].find("}}") {
+                Some(end) => start + 2 + end,
+                None => break,
+            };
+            let segment = Self::parse_placeholder(rest[start + 2..end].trim());
+            literal.push_str(&rest[..start]);
+            match segment {
+                Some(segment) => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut literal)));
+                    }
+                    segments.push(segment);
+                }
+                None => literal.push_str(&rest[start..end + 2]),
+            }
+            rest = &rest[end + 2..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Text(literal));
+        }
+        Template { segments }
+    }
+
+    fn parse_placeholder(placeholder: &str) -> Option<Segment> {
+        let (name, arg) = match placeholder.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg.trim())),
+            None => (placeholder, None),
+        };
+        match (name, arg) {
+            ("date", arg) => Some(Segment::Date(arg.unwrap_or(DEFAULT_DATE_FORMAT).to_string())),
+            ("clipboard", None) => Some(Segment::Clipboard),
+            ("uuid", None) => Some(Segment::Uuid),
+            ("input", Some(arg)) if !arg.is_empty() => Some(Segment::Input(arg.to_string())),
+            ("cursor", None) => Some(Segment::Cursor),
+            _ => None,
+        }
+    }
+
+    /// 文本中是否有可以展开的占位符
+    pub fn has_placeholders(text: &str) -> bool {
+        Template::parse(text).segments.iter().any(|segment| !matches!(segment, Segment::Text(_)))
+    }
+
+    /// 需要用户输入的字段，按出现顺序去重
+    pub fn inputs(&self) -> Vec<String> {
+        let mut ret: Vec<String> = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Input(name) = segment {
+                if !ret.contains(name) {
+                    ret.push(name.clone());
+                }
+            }
+        }
+        ret
+    }
+
+    /// 展开模板，clipboard 为当前剪贴板中的文本
+    pub fn expand(&self, clipboard: Option<&str>, inputs: &HashMap<String, String>) -> Result<Expanded> {
+        let mut text = String::new();
+        let mut cursor = None;
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(s) => text.push_str(s),
+                Segment::Date(format) => write!(text, "{}", Local::now().format(format))
+                    .map_err(|_| TemplateError::InvalidDateFormat(format.clone()))?,
+                Segment::Clipboard => text.push_str(clipboard.unwrap_or_default()),
+                Segment::Uuid => text.push_str(&uuid::Uuid::new_v4().to_string()),
+                Segment::Input(name) => match inputs.get(name) {
+                    Some(value) => text.push_str(value),
+                    None => return Err(TemplateError::MissingInput(name.clone())),
+                },
+                // 只认第一个光标标记
+                Segment::Cursor => if cursor.is_none() {
+                    cursor = Some(text.len());
+                },
+            }
+        }
+        // 按键左移一次移过一个字素簇，如 emoji 或 \r\n，而不是一个 char
+        let cursor_offset = cursor.map(|cursor| text[cursor..].graphemes(true).count());
+        Ok(Expanded { text, cursor_offset })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(text: &str, inputs: &[(&str, &str)]) -> Result<Expanded> {
+        let inputs = inputs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Template::parse(text).expand(Some("clip"), &inputs)
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept() {
+        assert!(!Template::has_placeholders("{{name}} {{ clipboard:x }} {{input:}}"));
+        assert_eq!(expand("a {{name}} {{clipboard}} b", &[]).unwrap().text, "a {{name}} clip b");
+    }
+
+    #[test]
+    fn unterminated_placeholder_is_literal() {
+        assert_eq!(expand("{{clipboard}} {{date", &[]).unwrap().text, "clip {{date");
+        assert_eq!(expand("}} {{", &[]).unwrap().text, "}} {{");
+    }
+
+    #[test]
+    fn duplicate_inputs_are_asked_once() {
+        let template = Template::parse("{{input:Name}} {{input: Team }} {{input:Name}}");
+        assert_eq!(template.inputs(), vec!["Name", "Team"]);
+        let expanded = expand("{{input:Name}}-{{input:Name}}", &[("Name", "x")]).unwrap();
+        assert_eq!(expanded.text, "x-x");
+        assert!(matches!(expand("{{input:Name}}", &[]), Err(TemplateError::MissingInput(name)) if name == "Name"));
+    }
+
+    #[test]
+    fn cursor_offset_counts_graphemes() {
+        assert_eq!(expand("no cursor", &[]).unwrap().cursor_offset, None);
+        assert_eq!(expand("ab{{cursor}}", &[]).unwrap().cursor_offset, Some(0));
+        // 组合字符、带肤色的 emoji 和 \r\n 各算一个
+        let expanded = expand("(){{cursor}}e\u{301}\u{1F44D}\u{1F3FD}\r\n{{cursor}}x", &[]).unwrap();
+        assert_eq!(expanded.text, "()e\u{301}\u{1F44D}\u{1F3FD}\r\nx");
+        assert_eq!(expanded.cursor_offset, Some(4));
+    }
+
+    #[test]
+    fn invalid_date_format_is_rejected() {
+        assert!(matches!(expand("{{date:%Q}}", &[]), Err(TemplateError::InvalidDateFormat(format)) if format == "%Q"));
+        assert_eq!(expand("{{date:%%}}", &[]).unwrap().text, "%");
+    }
+}
@@ -1,13 +1,22 @@
-use swift_rs::*;
-use tauri::Window;
-use std::ffi::c_void;
+cfg_if::cfg_if! {
+    // Swift 实现的原生接口只在 macOS 上链接
+    if #[cfg(target_os = "macos")] {
+        use swift_rs::*;
+        use tauri::Window;
+        use std::ffi::c_void;
 
-pub type NSObject = *mut c_void;
-swift_fn!(get_file_icon_base64(path: &str) -> String);
-swift_fn!(get_file_thumbnail_base64(path: &str) -> String);
-swift_fn!(set_window_top_level(window: NSObject));
-swift_fn!(paste() -> String);
+        pub type NSObject = *mut c_void;
+        swift_fn!(get_file_icon_base64(path: &str) -> String);
+        swift_fn!(get_file_thumbnail_base64(path: &str) -> String);
+        swift_fn!(set_window_top_level(window: NSObject));
+        swift_fn!(paste() -> String);
+        swift_fn!(move_cursor_left(count: isize));
+        swift_fn!(type_text(text: &str, delay_ms: isize));
+    }
+}
 
+/// 只有 macOS 上能生成缩略图
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub enum Data {
     Raw(Vec<u8>),
     Base64(String),
@@ -32,16 +41,33 @@ impl QuickLook {
         }
         Ok(Data::Base64(thumbnail.to_string()))
     }
+
+    /// 其他平台没有缩略图，前端显示默认图标
+    #[cfg(not(target_os = "macos"))]
+    pub fn thumbnail(_path: &str) -> Result<Data, String> {
+        Err("thumbnail is only supported on macOS".to_string())
+    }
 }
 
+#[cfg(target_os = "macos")]
 pub struct ClipboardUtil;
 
+#[cfg(target_os = "macos")]
 impl ClipboardUtil {
     /// 向系统发送粘贴事件
-    #[cfg(target_os = "macos")]
     pub fn paste_event() -> String {
         paste().to_string()
     }
+
+    /// 向系统发送 count 次左方向键，用于把光标移回片段中的 {{cursor}} 处
+    pub fn move_cursor_left(count: usize) {
+        move_cursor_left(count as isize)
+    }
+
+    /// 通过 CGEvent 逐个输入字符，每个字符之间间隔 delay_ms 毫秒
+    pub fn type_text(text: &str, delay_ms: u64) {
+        type_text(text.into(), delay_ms as isize)
+    }
 }
 
 pub struct ImageUtil;
@@ -52,8 +78,10 @@ impl ImageUtil {
     }
 }
 
+#[cfg(target_os = "macos")]
 pub struct WindowUtil;
 
+#[cfg(target_os = "macos")]
 impl WindowUtil {
     pub fn set_window_top_level(window: &Window) {
         set_window_top_level(window.ns_window().unwrap())
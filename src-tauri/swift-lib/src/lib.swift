@@ -62,6 +62,39 @@ func paste() -> SRString {
     return SRString();
 }
 
+@_cdecl("move_cursor_left")
+func moveCursorLeft(count: Int) {
+    let source = CGEventSource(stateID: .hidSystemState)
+    // 123 为左方向键
+    for _ in 0..<count {
+        CGEvent(keyboardEventSource: source, virtualKey: 123, keyDown: true)?.post(tap: .cghidEventTap)
+        CGEvent(keyboardEventSource: source, virtualKey: 123, keyDown: false)?.post(tap: .cghidEventTap)
+    }
+}
+
+@_cdecl("type_text")
+func typeText(text: SRString, delayMs: Int) {
+    let source = CGEventSource(stateID: .hidSystemState)
+    for character in text.to_string() {
+        // 回车和 Tab 用真实按键，其他字符通过 Unicode 字符串输入，不受键盘布局影响
+        let virtualKey: CGKeyCode = character == "\n" || character == "\r\n" ? 36 : character == "\t" ? 48 : 0
+        guard let down = CGEvent(keyboardEventSource: source, virtualKey: virtualKey, keyDown: true),
+              let up = CGEvent(keyboardEventSource: source, virtualKey: virtualKey, keyDown: false) else {
+            continue
+        }
+        if virtualKey == 0 {
+            let utf16 = Array(String(character).utf16)
+            down.keyboardSetUnicodeString(stringLength: utf16.count, unicodeString: utf16)
+            up.keyboardSetUnicodeString(stringLength: utf16.count, unicodeString: utf16)
+        }
+        down.post(tap: .cghidEventTap)
+        up.post(tap: .cghidEventTap)
+        if delayMs > 0 {
+            usleep(UInt32(delayMs * 1000))
+        }
+    }
+}
+
 @_cdecl("set_window_top_level")
 func setWindowTopLevel(window: NSWindow) {
     window.level = .mainMenu + 1
@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RtfVO } from "./RtfVO";
+
+/**
+ * 返回给前端的剪贴板记录，TypeScript 定义由 ts-rs 在 cargo test 时生成到 src/bindings/
+ */
+export type ClipboardRecordVO = { id: number, 
+/**
+ * 创建时间，毫秒时间戳
+ */
+time: number, description: string, 
+/**
+ * 关联目录中片段的名称
+ */
+name?: string, 
+/**
+ * 敏感内容，粘贴后会定时清除剪贴板
+ */
+secret: boolean, } & ({ "type": "text", data: string, } | { "type": "snippet", data: string, inputs: Array<string>, } | { "type": "rtf", data: RtfVO, } | { "type": "image", data: string, } | { "type": "file", data: string, thumbnail: string, });
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 错误码
+ */
+export type ErrorCode = "NotFound" | "Forbidden" | "InvalidArgument" | "InvalidFormat" | "StorageFailure" | "ClipboardUnavailable" | "PasteFailed" | "SyncFailure" | "Internal";
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ErrorCode } from "./ErrorCode";
+
+/**
+ * CmdError 序列化后的结构
+ */
+export type ErrorVO = { code: ErrorCode, message: string, 
+/**
+ * 原始的错误信息，用于排查问题
+ */
+detail?: string, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PasteBackend } from "./PasteBackend";
+import type { PasteKeys } from "./PasteKeys";
+
+/**
+ * 已经发送的粘贴按键
+ */
+export type Keystroke = { backend: PasteBackend, keys: PasteKeys, 
+/**
+ * 接收按键的窗口类名，无法获取时为空
+ */
+windowClass?: string, };
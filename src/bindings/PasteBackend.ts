@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 发送按键的方式，前端的类型中包含所有平台的，各平台只会构造其中一部分
+ */
+export type PasteBackend = "AppleScript" | "CgEvent" | "XTest" | "Uinput";
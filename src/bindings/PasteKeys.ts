@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 粘贴使用的组合键
+ */
+export type PasteKeys = "CmdV" | "CtrlV" | "CtrlShiftV";
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 选中记录后的操作
+ */
+export type PasteMode = "copyOnly" | "paste" | "pasteAndRestore";
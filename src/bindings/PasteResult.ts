@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Keystroke } from "./Keystroke";
+import type { TypedText } from "./TypedText";
+
+/**
+ * 粘贴命令的结果
+ */
+export type PasteResult = { "status": "copied" } | { "status": "pasted" } & Keystroke | { "status": "typed" } & TypedText | { "status": "waitingForInput" };
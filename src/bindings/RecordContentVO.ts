@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { RtfVO } from "./RtfVO";
+
+/**
+ * 不同类型记录的内容，用 type 字段区分
+ */
+export type RecordContentVO = { "type": "text", data: string, } | { "type": "snippet", data: string, inputs: Array<string>, } | { "type": "rtf", data: RtfVO, } | { "type": "image", data: string, } | { "type": "file", data: string, thumbnail: string, };
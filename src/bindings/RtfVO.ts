@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RtfVO = { 
+/**
+ * base64 编码的 RTF
+ */
+rtf: string, 
+/**
+ * 字符数
+ */
+text: number, };
@@ -0,0 +1,51 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Locale } from "./Locale";
+import type { PasteMode } from "./PasteMode";
+import type { ShortcutSettings } from "./ShortcutSettings";
+import type { TraySettings } from "./TraySettings";
+import type { WindowLayout } from "./WindowLayout";
+
+/**
+ * 应用设置，文件中缺少的字段使用默认值
+ */
+export type Settings = { 
+/**
+ * 界面语言，为空时跟随系统
+ */
+locale: Locale | null, 
+/**
+ * 抽屉的布局
+ */
+layout: WindowLayout, 
+/**
+ * 窗口高度，侧边栏布局时使用屏幕高度
+ */
+windowHeight: number, 
+/**
+ * 侧边栏和弹出窗口的宽度，顶部和底部布局时使用屏幕宽度
+ */
+windowWidth: number, 
+/**
+ * 剪贴板历史保留的条数
+ */
+historyLimit: number, 
+/**
+ * 清理剪贴板历史的间隔，秒
+ */
+gcInterval: number, 
+/**
+ * 没有指定粘贴方式时使用的默认方式
+ */
+pasteMode: PasteMode, 
+/**
+ * 粘贴敏感内容后多少秒清空剪贴板，为 0 时不清空
+ */
+secretClearDelay: number, 
+/**
+ * 日志级别，为空时使用环境变量 DRAWER_LOG 或 info
+ */
+logLevel: string | null, 
+/**
+ * 是否在日志中记录剪贴板内容，为空时使用环境变量 DRAWER_LOG_CONTENT
+ */
+logContent: boolean | null, shortcuts: ShortcutSettings, tray: TraySettings, };
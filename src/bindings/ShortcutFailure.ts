@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 注册失败的快捷键，通常是被其他应用占用了
+ */
+export type ShortcutFailure = { action: string, accelerator: string, reason: string, };
@@ -0,0 +1,26 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 全局快捷键设置，为空时不注册
+ */
+export type ShortcutSettings = { 
+/**
+ * 显示或隐藏抽屉
+ */
+toggle: string | null, 
+/**
+ * 去掉剪贴板内容的格式后粘贴
+ */
+pastePlain: string | null, 
+/**
+ * 直接粘贴最近的第 1～9 条记录
+ */
+pasteNth: Array<string | null>, 
+/**
+ * 暂停或恢复记录剪贴板
+ */
+pauseCapture: string | null, 
+/**
+ * 打开抽屉并进入搜索
+ */
+openSearch: string | null, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ShortcutFailure } from "./ShortcutFailure";
+import type { ShortcutSettings } from "./ShortcutSettings";
+
+/**
+ * 当前的快捷键设置和注册失败的快捷键
+ */
+export type ShortcutsVO = { settings: ShortcutSettings, failures: Array<ShortcutFailure>, };
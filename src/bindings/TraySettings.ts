@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 托盘菜单的文字，为空时使用当前语言的默认文字
+ */
+export type TraySettings = { about: string | null, quit: string | null, };
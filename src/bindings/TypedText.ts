@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PasteBackend } from "./PasteBackend";
+
+/**
+ * 逐个字符模拟键盘输入的结果
+ */
+export type TypedText = { backend: PasteBackend, 
+/**
+ * 输入的字符数
+ */
+chars: number, };
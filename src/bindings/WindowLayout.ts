@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * 抽屉的布局
+ */
+export type WindowLayout = "bottomStrip" | "topStrip" | "sidePanel" | "centeredPopup";